@@ -57,7 +57,6 @@ print(rates)
 
 grid = spdc.optimum_range(100).to_wavelength_space()
 jsi = get_jsi(spdc, grid)
-jsi = np.reshape(jsi, (100, 100))
 x_values = np.array(grid.x_values()) * 1e9
 y_values = np.array(grid.y_values()) * 1e9
 
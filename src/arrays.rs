@@ -0,0 +1,144 @@
+use crate::*;
+use numpy::ndarray::{ArrayD, IxDyn};
+use numpy::{PyReadonlyArrayDyn, PyUntypedArrayMethods};
+use pyo3::exceptions::PyValueError;
+use rayon::prelude::*;
+
+/// A sequence of floats passed in from python
+///
+/// Numpy float64 arrays are read in place. Anything else is extracted as a
+/// sequence of floats.
+#[derive(FromPyObject)]
+pub(crate) enum FloatArray<'py> {
+  #[pyo3(transparent)]
+  Array(PyReadonlyArrayDyn<'py, f64>),
+  #[pyo3(transparent)]
+  List(Vec<f64>),
+}
+
+impl FloatArray<'_> {
+  /// The shape of the input. Sequences are always one dimensional.
+  pub(crate) fn shape(&self) -> Vec<usize> {
+    match self {
+      FloatArray::Array(arr) => arr.shape().to_vec(),
+      FloatArray::List(list) => vec![list.len()],
+    }
+  }
+
+  /// Map every value (in row-major order) into a new vector
+  pub(crate) fn map<T, F: FnMut(f64) -> T>(&self, f: F) -> Vec<T> {
+    match self {
+      FloatArray::Array(arr) => arr.as_array().iter().copied().map(f).collect(),
+      FloatArray::List(list) => list.iter().copied().map(f).collect(),
+    }
+  }
+
+  /// Map a one dimensional sequence into a new vector
+  ///
+  /// For results returned as lists, which could not keep the shape of a
+  /// multidimensional array.
+  pub(crate) fn map_series<T, F: FnMut(f64) -> T>(&self, name: &str, f: F) -> PyResult<Vec<T>> {
+    match self.shape().as_slice() {
+      [_] => Ok(self.map(f)),
+      shape => Err(PyValueError::new_err(format!(
+        "Expected a one dimensional array of {}, got shape {:?}",
+        name, shape
+      ))),
+    }
+  }
+
+  /// Map signal-idler pairs into a flat interleaved vector
+  ///
+  /// Accepts either a flat sequence of interleaved `[s0, i0, s1, i1, ...]` values
+  /// or an `(N, 2)` array of `[signal, idler]` rows.
+  pub(crate) fn map_pairs<T, F: FnMut(f64) -> T>(&self, f: F) -> PyResult<Vec<T>> {
+    match self.shape().as_slice() {
+      [n] if n % 2 == 0 => Ok(self.map(f)),
+      [_, 2] => Ok(self.map(f)),
+      shape => Err(PyValueError::new_err(format!(
+        "Expected a flat array of even length or an (N, 2) array of signal-idler pairs, got shape {:?}",
+        shape
+      ))),
+    }
+  }
+}
+
+/// Either a single float or an array of floats passed in from python
+#[derive(FromPyObject)]
+pub(crate) enum FloatOrArray<'py> {
+  #[pyo3(transparent)]
+  Float(f64),
+  #[pyo3(transparent)]
+  Array(FloatArray<'py>),
+}
+
+impl FloatOrArray<'_> {
+  /// Copy the input into an owned n-dimensional array (zero dimensional for floats)
+  fn to_ndarray(&self) -> ArrayD<f64> {
+    match self {
+      FloatOrArray::Float(value) => ArrayD::from_elem(IxDyn(&[]), *value),
+      FloatOrArray::Array(FloatArray::Array(arr)) => arr.as_array().to_owned(),
+      FloatOrArray::Array(FloatArray::List(list)) => {
+        ArrayD::from_shape_vec(IxDyn(&[list.len()]), list.clone())
+          .expect("a list is always one dimensional")
+      }
+    }
+  }
+}
+
+/// The shape two arrays broadcast to, following numpy's rules
+fn broadcast_shape(a: &[usize], b: &[usize]) -> PyResult<Vec<usize>> {
+  let ndim = a.len().max(b.len());
+  let dim = |shape: &[usize], i: usize| (i + shape.len()).checked_sub(ndim).map_or(1, |j| shape[j]);
+  (0..ndim)
+    .map(|i| match (dim(a, i), dim(b, i)) {
+      (x, y) if x == y || y == 1 => Ok(x),
+      (1, y) => Ok(y),
+      _ => Err(PyValueError::new_err(format!(
+        "Operands could not be broadcast together with shapes {:?} {:?}",
+        a, b
+      ))),
+    })
+    .collect()
+}
+
+/// Evaluate a function element-wise over two broadcast inputs, like a numpy ufunc
+///
+/// If both inputs are floats the result is a single value, otherwise the
+/// values are computed in parallel (without the GIL) and returned as a numpy
+/// array of the broadcast shape.
+pub(crate) fn par_map_broadcast<T, F>(
+  py: Python<'_>,
+  a: &FloatOrArray<'_>,
+  b: &FloatOrArray<'_>,
+  f: F,
+) -> PyResult<PyObject>
+where
+  T: numpy::Element + IntoPy<PyObject> + Send,
+  F: Fn(f64, f64) -> T + Send + Sync,
+{
+  if let (FloatOrArray::Float(a), FloatOrArray::Float(b)) = (a, b) {
+    return Ok(f(*a, *b).into_py(py));
+  }
+  let (a, b) = (a.to_ndarray(), b.to_ndarray());
+  let shape = broadcast_shape(a.shape(), b.shape())?;
+  let pairs: Vec<(f64, f64)> = {
+    // both broadcasts succeed since the shape is compatible
+    let a = a.broadcast(shape.clone()).unwrap();
+    let b = b.broadcast(shape.clone()).unwrap();
+    a.iter().copied().zip(b.iter().copied()).collect()
+  };
+  let values = py.allow_threads(|| pairs.into_par_iter().map(|(a, b)| f(a, b)).collect());
+  Ok(to_shaped_pyarray(py, values, shape)?.into_any().unbind())
+}
+
+/// Evaluate a function element-wise over an input, like a numpy ufunc
+///
+/// See [`par_map_broadcast`].
+pub(crate) fn par_map<T, F>(py: Python<'_>, a: &FloatOrArray<'_>, f: F) -> PyResult<PyObject>
+where
+  T: numpy::Element + IntoPy<PyObject> + Send,
+  F: Fn(f64) -> T + Send + Sync,
+{
+  par_map_broadcast(py, a, &FloatOrArray::Float(0.), |a, _| f(a))
+}
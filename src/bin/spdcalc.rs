@@ -0,0 +1,490 @@
+//! Command line interface for batch SPDC calculations from config files
+//!
+//! Reads a YAML or JSON config (the `SPDCConfig` format), runs a calculation over
+//! a grid of signal and idler frequencies or wavelengths, and writes the result
+//! as CSV, NPY or JSON. This uses spdcalc directly, so no python is needed.
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::json;
+use serde_yaml::Value;
+use spdcalc::dim::{
+  f64prefixes::{FEMTO, NANO},
+  ucum::{HZ, M, RAD, S},
+};
+use spdcalc::math::Integrator;
+use spdcalc::utils::Steps;
+use spdcalc::{FrequencySpace, WavelengthSpace, SPDC};
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// Batch SPDC calculations from YAML or JSON config files
+#[derive(Parser)]
+#[command(name = "spdcalc", version)]
+struct Cli {
+  #[command(subcommand)]
+  calculation: Calculation,
+
+  /// The config file (YAML or JSON), or "-" for stdin
+  #[arg(global = true, default_value = "-")]
+  config: PathBuf,
+
+  /// Grid of signal and idler values: "auto[:STEPS]" for the optimum frequency
+  /// range, or "wavelength_nm:MIN:MAX:STEPS[,MIN:MAX:STEPS]" or
+  /// "frequency_rad_per_s:MIN:MAX:STEPS[,MIN:MAX:STEPS]" for the signal
+  /// (and idler, which defaults to the signal) axis
+  #[arg(short, long, global = true, default_value = "auto:100")]
+  grid: Grid,
+
+  /// Integrator as YAML, e.g. "{ method: Simpson, divs: 50 }"
+  #[arg(long, global = true)]
+  integrator: Option<String>,
+
+  /// The output file, or stdout if not given
+  #[arg(short, long, global = true)]
+  output: Option<PathBuf>,
+
+  /// The output format. Defaults to the extension of the output file, or json.
+  #[arg(short, long, global = true)]
+  format: Option<Format>,
+}
+
+#[derive(Subcommand)]
+enum Calculation {
+  /// Normalized joint spectral intensity over the grid
+  Jsi,
+  /// Symmetric, signal and idler efficiencies, and coincidence and singles rates
+  Efficiencies,
+  /// HOM dip time delay and visibility, or the coincidence rate over time delays
+  Hom {
+    /// Time delays in femtoseconds as "MIN:MAX:STEPS" for a rate series
+    #[arg(long)]
+    delays_fs: Option<String>,
+  },
+  /// Schmidt number of the joint spectral amplitude over the grid
+  Schmidt,
+  /// The optimum setup (crystal angle or poling period, idler and waist positions)
+  Optimum,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+  Csv,
+  Npy,
+  Json,
+}
+
+/// A grid of signal (x) and idler (y) values
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Grid {
+  /// The optimum frequency range with this many steps per axis
+  Auto(usize),
+  /// Wavelengths in nm
+  Wavelength((f64, f64, usize), (f64, f64, usize)),
+  /// Frequencies in rad/s
+  Frequency((f64, f64, usize), (f64, f64, usize)),
+}
+
+/// Parse "MIN:MAX:STEPS"
+fn parse_steps(spec: &str) -> std::result::Result<(f64, f64, usize), String> {
+  let parts: Vec<&str> = spec.split(':').collect();
+  let [min, max, steps] = parts.as_slice() else {
+    return Err(format!("expected MIN:MAX:STEPS, got '{}'", spec));
+  };
+  let number = |s: &str| {
+    s.trim()
+      .parse::<f64>()
+      .map_err(|e| format!("invalid number '{}': {}", s, e))
+  };
+  let steps = steps
+    .trim()
+    .parse::<usize>()
+    .map_err(|e| format!("invalid number of steps '{}': {}", steps, e))?;
+  if steps < 2 {
+    return Err(format!("a range needs at least 2 steps, got {}", steps));
+  }
+  Ok((number(min)?, number(max)?, steps))
+}
+
+impl std::str::FromStr for Grid {
+  type Err = String;
+
+  fn from_str(spec: &str) -> std::result::Result<Self, Self::Err> {
+    let (kind, axes) = spec.split_once(':').unwrap_or((spec, ""));
+    if kind == "auto" {
+      return match axes {
+        "" => Ok(Grid::Auto(100)),
+        steps => match steps.parse() {
+          Ok(0) => Err("the grid needs at least 1 step".into()),
+          Ok(steps) => Ok(Grid::Auto(steps)),
+          Err(e) => Err(format!("invalid number of steps '{}': {}", steps, e)),
+        },
+      };
+    }
+    let (x, y) = axes.split_once(',').unwrap_or((axes, axes));
+    let (x, y) = (parse_steps(x)?, parse_steps(y)?);
+    match kind {
+      "wavelength_nm" => Ok(Grid::Wavelength(x, y)),
+      "frequency_rad_per_s" => Ok(Grid::Frequency(x, y)),
+      other => Err(format!(
+        "unknown grid kind '{}'. Expected one of: auto, wavelength_nm, frequency_rad_per_s",
+        other
+      )),
+    }
+  }
+}
+
+impl Grid {
+  /// The range as a frequency space, as needed for integrated quantities
+  fn frequency_space(self, spdc: &SPDC) -> FrequencySpace {
+    match self {
+      Grid::Auto(steps) => spdc.optimum_range(steps),
+      Grid::Wavelength(..) => FrequencySpace::from_wavelength_space(self.wavelength_space()),
+      Grid::Frequency(x, y) => {
+        let hz = |(min, max, steps): (f64, f64, usize)| (min * RAD * HZ, max * RAD * HZ, steps);
+        FrequencySpace::new(hz(x), hz(y))
+      }
+    }
+  }
+
+  fn wavelength_space(self) -> WavelengthSpace {
+    let Grid::Wavelength(x, y) = self else {
+      unreachable!("only wavelength grids are wavelength spaces")
+    };
+    let nm = |(min, max, steps): (f64, f64, usize)| (min * NANO * M, max * NANO * M, steps);
+    WavelengthSpace::new(nm(x), nm(y))
+  }
+}
+
+/// The result of a calculation
+enum Output {
+  /// Named values
+  Record(Vec<(&'static str, f64)>),
+  /// Named columns of equal length
+  Table(Vec<(String, Vec<f64>)>),
+  /// Values over the grid, in rows of idler values by columns of signal values
+  Grid {
+    signal: (String, Vec<f64>),
+    idler: (String, Vec<f64>),
+    name: &'static str,
+    values: Vec<f64>,
+  },
+  /// An SPDC config
+  Config(Box<spdcalc::SPDCConfig>),
+}
+
+/// Read a config, rejecting the extensions that only the python bindings support
+fn read_config(path: &Path) -> Result<SPDC> {
+  let text = if path == Path::new("-") {
+    std::io::read_to_string(std::io::stdin())?
+  } else {
+    std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?
+  };
+  // YAML is a superset of JSON, so this reads both
+  let value: Value = serde_yaml::from_str(&text)?;
+  let spectrum = value.get("pump").and_then(|pump| pump.get("spectrum"));
+  if spectrum.is_some_and(|s| s.as_str() != Some("gaussian")) {
+    return Err(
+      "pump.spectrum: custom pump spectra are only supported by the python bindings".into(),
+    );
+  }
+  for beam in ["pump", "signal", "idler"] {
+    if value[beam]["waist_um"].is_sequence() {
+      return Err(
+        format!(
+          "{}.waist_um: elliptical waists are only supported by the python bindings",
+          beam
+        )
+        .into(),
+      );
+    }
+  }
+  Ok(serde_yaml::from_str(&text)?)
+}
+
+/// Values of the steps of an axis, in the given unit
+fn axis<T>(steps: (T, T, usize), unit: T) -> Vec<f64>
+where
+  T: Copy + std::ops::Div<T>,
+  <T as std::ops::Div<T>>::Output: std::ops::Deref<Target = f64>,
+  Steps<T>: IntoIterator<Item = T>,
+{
+  Steps::from(steps)
+    .into_iter()
+    .map(|v| *(v / unit))
+    .collect()
+}
+
+fn jsi(spdc: &SPDC, grid: Grid, integrator: Integrator) -> Output {
+  let spectrum = spdc.joint_spectrum(integrator);
+  match grid {
+    Grid::Wavelength(..) => {
+      let ws = grid.wavelength_space();
+      let (x, y) = (ws.steps().0, ws.steps().1);
+      Output::Grid {
+        signal: ("signal_nm".into(), axis(x, NANO * M)),
+        idler: ("idler_nm".into(), axis(y, NANO * M)),
+        name: "jsi",
+        values: spectrum.jsi_normalized_range(ws),
+      }
+    }
+    _ => {
+      let fs = grid.frequency_space(spdc);
+      let (x, y) = (fs.steps().0, fs.steps().1);
+      Output::Grid {
+        signal: ("signal_rad_per_s".into(), axis(x, RAD * HZ)),
+        idler: ("idler_rad_per_s".into(), axis(y, RAD * HZ)),
+        name: "jsi",
+        values: spectrum.jsi_normalized_range(fs),
+      }
+    }
+  }
+}
+
+fn efficiencies(spdc: &SPDC, grid: Grid, integrator: Integrator) -> Output {
+  let e = spdc.efficiencies(grid.frequency_space(spdc), integrator);
+  Output::Record(vec![
+    ("symmetric", e.symmetric),
+    ("signal", e.signal),
+    ("idler", e.idler),
+    ("coincidences_hz", *(e.coincidences / HZ)),
+    ("signal_singles_hz", *(e.signal_singles / HZ)),
+    ("idler_singles_hz", *(e.idler_singles / HZ)),
+  ])
+}
+
+fn hom(spdc: &SPDC, grid: Grid, integrator: Integrator, delays_fs: Option<&str>) -> Result<Output> {
+  let range = grid.frequency_space(spdc);
+  let Some(delays_fs) = delays_fs else {
+    let (delay, visibility) = spdc.hom_visibility(range, integrator);
+    return Ok(Output::Record(vec![
+      ("time_delay_s", *(delay / S)),
+      ("visibility", visibility),
+    ]));
+  };
+  let (min, max, steps) = parse_steps(delays_fs)?;
+  let delays = Steps::from((min * FEMTO * S, max * FEMTO * S, steps));
+  let rates = spdc.hom_rate_series(delays, range, integrator);
+  Ok(Output::Table(vec![
+    (
+      "time_delay_s".into(),
+      axis((min * FEMTO * S, max * FEMTO * S, steps), S),
+    ),
+    ("rate".into(), rates),
+  ]))
+}
+
+fn schmidt(spdc: &SPDC, grid: Grid, integrator: Integrator) -> Result<Output> {
+  let number = spdc
+    .joint_spectrum(integrator)
+    .schmidt_number(grid.frequency_space(spdc))?;
+  Ok(Output::Record(vec![("schmidt_number", number)]))
+}
+
+/// Write an array in the NPY format (version 1.0, little endian doubles)
+fn write_npy(w: &mut dyn Write, shape: &[usize], values: &[f64]) -> std::io::Result<()> {
+  let shape = match shape {
+    [n] => format!("({},)", n),
+    _ => format!(
+      "({})",
+      shape
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+    ),
+  };
+  let mut header = format!(
+    "{{'descr': '<f8', 'fortran_order': False, 'shape': {}, }}",
+    shape
+  );
+  // the magic string, version, header length and header end in a newline at a multiple of 64 bytes
+  let unpadded = 10 + header.len() + 1;
+  header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+  header.push('\n');
+  w.write_all(b"\x93NUMPY\x01\x00")?;
+  w.write_all(&(header.len() as u16).to_le_bytes())?;
+  w.write_all(header.as_bytes())?;
+  for v in values {
+    w.write_all(&v.to_le_bytes())?;
+  }
+  Ok(())
+}
+
+/// Write as CSV, with numbers in scientific notation where that is shorter
+fn write_csv(w: &mut dyn Write, output: &Output) -> Result<()> {
+  match output {
+    Output::Record(values) => {
+      let names: Vec<&str> = values.iter().map(|(name, _)| *name).collect();
+      let values: Vec<String> = values.iter().map(|(_, v)| format!("{:?}", v)).collect();
+      writeln!(w, "{}", names.join(","))?;
+      writeln!(w, "{}", values.join(","))?;
+    }
+    Output::Table(columns) => {
+      let names: Vec<&str> = columns.iter().map(|(name, _)| name.as_str()).collect();
+      writeln!(w, "{}", names.join(","))?;
+      for row in 0..columns[0].1.len() {
+        let values: Vec<String> = columns
+          .iter()
+          .map(|(_, c)| format!("{:?}", c[row]))
+          .collect();
+        writeln!(w, "{}", values.join(","))?;
+      }
+    }
+    Output::Grid {
+      signal,
+      idler,
+      name,
+      values,
+    } => {
+      writeln!(w, "{},{},{}", signal.0, idler.0, name)?;
+      let rows = idler
+        .1
+        .iter()
+        .flat_map(|y| signal.1.iter().map(move |x| (x, y)));
+      for ((x, y), v) in rows.zip(values) {
+        writeln!(w, "{:?},{:?},{:?}", x, y, v)?;
+      }
+    }
+    Output::Config(_) => return Err("the optimum setup can only be written as json".into()),
+  }
+  Ok(())
+}
+
+fn write_json(w: &mut dyn Write, output: &Output) -> Result<()> {
+  let value = match output {
+    Output::Record(values) => values
+      .iter()
+      .map(|(name, v)| (name.to_string(), json!(v)))
+      .collect(),
+    Output::Table(columns) => columns
+      .iter()
+      .map(|(name, c)| (name.clone(), json!(c)))
+      .collect(),
+    Output::Grid {
+      signal,
+      idler,
+      name,
+      values,
+    } => {
+      let rows: Vec<&[f64]> = values.chunks(signal.1.len()).collect();
+      json!({ &signal.0: signal.1, &idler.0: idler.1, *name: rows })
+    }
+    Output::Config(config) => serde_json::to_value(config)?,
+  };
+  serde_json::to_writer_pretty(&mut *w, &value)?;
+  writeln!(w)?;
+  Ok(())
+}
+
+fn write(w: &mut dyn Write, output: &Output, format: Format) -> Result<()> {
+  match (format, output) {
+    (Format::Csv, _) => write_csv(w, output),
+    (Format::Json, _) => write_json(w, output),
+    (
+      Format::Npy,
+      Output::Grid {
+        signal,
+        idler,
+        values,
+        ..
+      },
+    ) => Ok(write_npy(w, &[idler.1.len(), signal.1.len()], values)?),
+    (Format::Npy, Output::Table(columns)) => {
+      let rows = columns[0].1.len();
+      let values: Vec<f64> = (0..rows)
+        .flat_map(|row| columns.iter().map(move |(_, c)| c[row]))
+        .collect();
+      Ok(write_npy(w, &[rows, columns.len()], &values)?)
+    }
+    (Format::Npy, _) => {
+      Err("only array results (jsi, hom with --delays-fs) can be written as npy".into())
+    }
+  }
+}
+
+fn run(cli: Cli) -> Result<()> {
+  let spdc = read_config(&cli.config)?;
+  let integrator: Integrator = match &cli.integrator {
+    Some(yaml) => serde_yaml::from_str(yaml).map_err(|e| format!("--integrator: {}", e))?,
+    None => Integrator::default(),
+  };
+  let output = match &cli.calculation {
+    Calculation::Jsi => jsi(&spdc, cli.grid, integrator),
+    Calculation::Efficiencies => efficiencies(&spdc, cli.grid, integrator),
+    Calculation::Hom { delays_fs } => hom(&spdc, cli.grid, integrator, delays_fs.as_deref())?,
+    Calculation::Schmidt => schmidt(&spdc, cli.grid, integrator)?,
+    Calculation::Optimum => Output::Config(Box::new(spdc.try_as_optimum()?.as_config())),
+  };
+
+  let format = cli.format.unwrap_or_else(|| {
+    let extension = cli.output.as_ref().and_then(|p| p.extension());
+    match extension.and_then(|e| e.to_str()) {
+      Some("csv") => Format::Csv,
+      Some("npy") => Format::Npy,
+      _ => Format::Json,
+    }
+  });
+  match &cli.output {
+    Some(path) => {
+      // only create the file once the output could be written
+      let mut buffer = Vec::new();
+      write(&mut buffer, &output, format)?;
+      std::fs::write(path, buffer)?;
+    }
+    None => write(&mut std::io::stdout().lock(), &output, format)?,
+  }
+  Ok(())
+}
+
+fn main() {
+  if let Err(e) = run(Cli::parse()) {
+    // output piped into e.g. head is closed early, which is not an error
+    let kind = match e.downcast_ref::<serde_json::Error>() {
+      Some(e) => e.io_error_kind(),
+      None => e.downcast_ref::<std::io::Error>().map(|e| e.kind()),
+    };
+    let closed = kind == Some(std::io::ErrorKind::BrokenPipe);
+    if closed {
+      return;
+    }
+    eprintln!("error: {}", e);
+    std::process::exit(1);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn grids_are_parsed() {
+    assert_eq!("auto".parse(), Ok(Grid::Auto(100)));
+    assert_eq!("auto:30".parse(), Ok(Grid::Auto(30)));
+    assert_eq!(
+      "wavelength_nm:1500:1600:10".parse(),
+      Ok(Grid::Wavelength((1500., 1600., 10), (1500., 1600., 10)))
+    );
+    assert_eq!(
+      "frequency_rad_per_s:1e15:2e15:5,1.1e15:1.2e15:6".parse(),
+      Ok(Grid::Frequency((1e15, 2e15, 5), (1.1e15, 1.2e15, 6)))
+    );
+    assert!("wavelength_nm:1500:1600".parse::<Grid>().is_err());
+    assert!("wavelength_nm:1500:1600:1".parse::<Grid>().is_err());
+    assert!("auto:0".parse::<Grid>().is_err());
+    assert!("time:1:2:3".parse::<Grid>().is_err());
+  }
+
+  #[test]
+  fn npy_header_is_aligned() {
+    let mut bytes = Vec::new();
+    write_npy(&mut bytes, &[2, 3], &[0.; 6]).unwrap();
+    let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+    assert_eq!((10 + header_len) % 64, 0);
+    assert_eq!(bytes.len(), 10 + header_len + 6 * 8);
+    let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+    assert!(header.contains("'shape': (2, 3)"));
+    assert!(header.ends_with('\n'));
+  }
+}
@@ -0,0 +1,525 @@
+use crate::*;
+use ::spdcalc::beam::BeamWaist;
+use ::spdcalc::dim::{f64prefixes::MICRO, ucum::M};
+use ::spdcalc::math::sigfigs;
+use ::spdcalc::SPDCConfig;
+use pyo3::exceptions::PyValueError;
+use serde_yaml::Value;
+
+/// Significant figures of rounded configs, as in spdcalc
+const SIG_FIGS_IN_CONFIG: u8 = 4;
+
+/// The beams whose waists may be elliptical, by config section
+pub(crate) const BEAMS: [&str; 3] = ["pump", "signal", "idler"];
+
+/// A text format of SPDC configs
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Format {
+  Yaml,
+  Json,
+  Toml,
+}
+
+impl Format {
+  /// The format of a config file, by its extension
+  pub(crate) fn from_path(path: &std::path::Path) -> Option<Self> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+      "yaml" | "yml" => Some(Format::Yaml),
+      "json" => Some(Format::Json),
+      "toml" => Some(Format::Toml),
+      _ => None,
+    }
+  }
+}
+
+impl<'py> FromPyObject<'py> for Format {
+  fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+    match ob.extract::<String>()?.to_lowercase().as_str() {
+      "yaml" | "yml" => Ok(Format::Yaml),
+      "json" => Ok(Format::Json),
+      "toml" => Ok(Format::Toml),
+      other => Err(PyValueError::new_err(format!(
+        "Unknown config format '{}'. Expected one of: yaml, json, toml",
+        other
+      ))),
+    }
+  }
+}
+
+/// Parts of a config that only these bindings understand
+///
+/// spdcalc ignores `pump.spectrum`, but would reject an elliptical
+/// `waist_um: [x, y]`, so those are replaced by the x waist before parsing.
+/// spdcalc also gives an explicit poling period the sign that phasematches,
+/// so a negative period (which reverses that sign) is applied afterwards.
+#[derive(Default)]
+pub(crate) struct Extensions {
+  pump_spectrum: PumpSpectrum,
+  /// The y waists (um) of the pump, signal and idler, if elliptical
+  pub(crate) waists_y_um: [Option<f64>; 3],
+  /// The poling period is negative
+  reversed_poling: bool,
+}
+
+impl Extensions {
+  /// Remove the extensions from a config value, collecting any problems with them
+  pub(crate) fn take(value: &mut Value, problems: &mut Vec<PyConfigError>) -> Self {
+    let mut extensions = Self {
+      reversed_poling: section(value, "periodic_poling")
+        .and_then(|pp| pp.get("poling_period_um"))
+        .and_then(Value::as_f64)
+        .is_some_and(|period| period < 0.),
+      ..Self::default()
+    };
+    if let Some(spectrum) = section(value, "pump").and_then(|pump| pump.remove("spectrum")) {
+      match serde_yaml::from_value(spectrum) {
+        Ok(spectrum) => extensions.pump_spectrum = spectrum,
+        Err(e) => problems.push(PyConfigError::at("pump.spectrum", e)),
+      }
+    }
+    for (beam, y) in BEAMS.iter().zip(&mut extensions.waists_y_um) {
+      let Some(waist) = section(value, beam).and_then(|s| s.get_mut("waist_um")) else {
+        continue;
+      };
+      if let Value::Sequence(xy) = waist {
+        let (x, wy) = match xy.as_slice() {
+          [x, wy] => (x.as_f64(), wy.as_f64()),
+          _ => (None, None),
+        };
+        let (Some(x), Some(wy)) = (x, wy) else {
+          problems.push(PyConfigError::at(
+            &format!("{}.waist_um", beam),
+            "expected a number or a list of two numbers [x, y]",
+          ));
+          continue;
+        };
+        *waist = x.into();
+        *y = Some(wy);
+      }
+    }
+    extensions
+  }
+
+  fn is_rewritten(&self) -> bool {
+    self.waists_y_um.iter().any(Option::is_some)
+  }
+
+  pub(crate) fn apply(self, spdc: &mut ::spdcalc::SPDC, idler_is_auto: bool) -> PumpSpectrum {
+    let [pump_y, signal_y, idler_y] = self.waists_y_um;
+    let elliptical = |waist: BeamWaist, y: Option<f64>| BeamWaist {
+      x: waist.x,
+      y: y.map_or(waist.y, |y| y * MICRO * M),
+    };
+    let pump = elliptical(spdc.pump.waist(), pump_y);
+    spdc.pump.set_waist(pump);
+    let signal = elliptical(spdc.signal.waist(), signal_y);
+    spdc.signal.set_waist(signal);
+    // an automatic idler is derived from the signal, including its waist
+    let idler = if idler_is_auto {
+      signal
+    } else {
+      elliptical(spdc.idler.waist(), idler_y)
+    };
+    spdc.idler.set_waist(idler);
+    if self.reversed_poling {
+      spdc.pp = spdc.pp.clone().with_period(-spdc.pp.signed_period());
+    }
+    self.pump_spectrum
+  }
+}
+
+/// Config fields that were `auto`, and so are calculated from the rest of the setup
+///
+/// spdcalc resolves these to numbers when parsing, so this remembers the intent
+/// for writing the config back and for recalculating them after changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct AutoFields {
+  pub(crate) crystal_theta: bool,
+  pub(crate) poling_period: bool,
+  pub(crate) signal_waist_position: bool,
+  /// The whole idler is derived from the signal and pump
+  pub(crate) idler: bool,
+  pub(crate) idler_waist_position: bool,
+}
+
+impl AutoFields {
+  /// The config paths of the fields, in the order of the struct
+  const PATHS: [&'static str; 5] = [
+    "crystal.theta_deg",
+    "periodic_poling.poling_period_um",
+    "signal.waist_position_um",
+    "idler",
+    "idler.waist_position_um",
+  ];
+
+  /// The auto fields of a config value. Missing fields that default to auto count as auto.
+  pub(crate) fn of(value: &Value) -> Self {
+    let is_auto = |section: &str, field: &str| match value.get(section).map(|s| s.get(field)) {
+      Some(Some(v)) => v.as_str() == Some("auto"),
+      Some(None) => true,
+      None => false,
+    };
+    let idler = idler_is_auto(value);
+    Self {
+      crystal_theta: is_auto("crystal", "theta_deg"),
+      poling_period: value
+        .get("periodic_poling")
+        .and_then(|pp| pp.get("poling_period_um"))
+        .is_some_and(|period| period.as_str() == Some("auto")),
+      signal_waist_position: is_auto("signal", "waist_position_um"),
+      idler,
+      idler_waist_position: idler || is_auto("idler", "waist_position_um"),
+    }
+  }
+
+  fn flags(&self) -> [bool; 5] {
+    [
+      self.crystal_theta,
+      self.poling_period,
+      self.signal_waist_position,
+      self.idler,
+      self.idler_waist_position,
+    ]
+  }
+
+  /// The config paths of the auto fields
+  pub(crate) fn paths(&self) -> Vec<&'static str> {
+    Self::PATHS
+      .iter()
+      .zip(self.flags())
+      .filter(|(_, auto)| *auto)
+      .map(|(path, _)| *path)
+      .collect()
+  }
+
+  /// The auto fields with the given config paths
+  pub(crate) fn from_paths<S: AsRef<str>>(paths: &[S]) -> Result<Self, PyConfigError> {
+    let mut flags = [false; 5];
+    for path in paths {
+      let path = path.as_ref();
+      let Some(index) = Self::PATHS.iter().position(|p| *p == path) else {
+        return Err(PyConfigError::at(
+          path,
+          format!(
+            "can not be auto. Expected one of: {}",
+            Self::PATHS.join(", ")
+          ),
+        ));
+      };
+      flags[index] = true;
+    }
+    let [crystal_theta, poling_period, signal_waist_position, idler, idler_waist_position] = flags;
+    Ok(Self {
+      crystal_theta,
+      poling_period,
+      signal_waist_position,
+      idler,
+      idler_waist_position: idler || idler_waist_position,
+    })
+  }
+
+  /// Make the idler explicit, as when one of its values is set
+  ///
+  /// An automatic idler always has an automatic waist position, which it keeps.
+  pub(crate) fn set_explicit_idler(&mut self) {
+    if self.idler {
+      self.idler = false;
+      self.idler_waist_position = true;
+    }
+  }
+
+  /// Write `auto` back into a config value for the auto fields
+  pub(crate) fn mark(&self, value: &mut Value) {
+    let auto = || Value::from("auto");
+    if self.crystal_theta {
+      if let Some(crystal) = section(value, "crystal") {
+        crystal.insert("theta_deg".into(), auto());
+      }
+    }
+    if self.poling_period {
+      if let Some(pp) = section(value, "periodic_poling") {
+        pp.insert("poling_period_um".into(), auto());
+      }
+    }
+    if self.signal_waist_position {
+      if let Some(signal) = section(value, "signal") {
+        signal.insert("waist_position_um".into(), auto());
+      }
+    }
+    if self.idler {
+      if let Some(config) = value.as_mapping_mut() {
+        config.insert("idler".into(), auto());
+      }
+    } else if self.idler_waist_position {
+      if let Some(idler) = section(value, "idler") {
+        idler.insert("waist_position_um".into(), auto());
+      }
+    }
+  }
+}
+
+/// A mutable config section (like `pump`), if it is a mapping
+fn section<'a>(value: &'a mut Value, name: &str) -> Option<&'a mut serde_yaml::Mapping> {
+  value.get_mut(name).and_then(Value::as_mapping_mut)
+}
+
+/// Parse config text in the given format into an untyped value
+pub(crate) fn parse_value(text: &str, format: Format) -> Result<Value, PyConfigError> {
+  Ok(match format {
+    Format::Yaml => serde_yaml::from_str(text)?,
+    Format::Json => serde_json::from_str(text)?,
+    Format::Toml => toml::from_str(text).map_err(|e| PyConfigError::from_toml(e, text))?,
+  })
+}
+
+/// Remove null values, which TOML can not represent
+///
+/// Every nullable config field defaults to null, so leaving them out is equivalent.
+pub(crate) fn without_nulls(value: Value) -> Value {
+  match value {
+    Value::Mapping(mapping) => Value::Mapping(
+      mapping
+        .into_iter()
+        .filter(|(_, v)| !v.is_null())
+        .map(|(k, v)| (k, without_nulls(v)))
+        .collect(),
+    ),
+    Value::Sequence(sequence) => sequence.into_iter().map(without_nulls).collect(),
+    value => value,
+  }
+}
+
+/// Whether the idler of a config value is calculated automatically
+pub(crate) fn idler_is_auto(value: &Value) -> bool {
+  !matches!(value.get("idler"), Some(Value::Mapping(_)))
+}
+
+/// Parse a config in the given format, including the extensions of these bindings
+pub(crate) fn parse_config(text: &str, format: Format) -> Result<SPDC, PyConfigError> {
+  let mut value = parse_value(text, format)?;
+  let mut problems = Vec::new();
+  let extensions = Extensions::take(&mut value, &mut problems);
+  if let Some(problem) = problems.into_iter().next() {
+    return Err(problem);
+  }
+  let auto = AutoFields::of(&value);
+  // parse the text itself when possible, since its errors have locations
+  let mut spdc: ::spdcalc::SPDC = if extensions.is_rewritten() {
+    serde_path_to_error::deserialize(value).map_err(PyConfigError::from_yaml_path)?
+  } else {
+    match format {
+      Format::Yaml => serde_path_to_error::deserialize(serde_yaml::Deserializer::from_str(text))
+        .map_err(PyConfigError::from_yaml_path)?,
+      Format::Json => {
+        serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_str(text))
+          .map_err(PyConfigError::from_json_path)?
+      }
+      Format::Toml => serde_path_to_error::deserialize(toml::Deserializer::new(text))
+        .map_err(|e| PyConfigError::from_toml_path(e, text))?,
+    }
+  };
+  let pump_spectrum = extensions.apply(&mut spdc, auto.idler);
+  Ok(SPDC(spdc, pump_spectrum, auto))
+}
+
+/// Parse a config value, including the extensions of these bindings
+pub(crate) fn parse_config_value(mut value: Value) -> Result<SPDC, PyConfigError> {
+  let mut problems = Vec::new();
+  let extensions = Extensions::take(&mut value, &mut problems);
+  if let Some(problem) = problems.into_iter().next() {
+    return Err(problem);
+  }
+  let auto = AutoFields::of(&value);
+  let mut spdc: ::spdcalc::SPDC =
+    serde_path_to_error::deserialize(value).map_err(PyConfigError::from_yaml_path)?;
+  let pump_spectrum = extensions.apply(&mut spdc, auto.idler);
+  Ok(SPDC(spdc, pump_spectrum, auto))
+}
+
+impl SPDC {
+  /// The config of this setup, including the extensions of these bindings
+  ///
+  /// Elliptical waists are written as `waist_um: [x, y]`, non-gaussian
+  /// pump spectra as `pump.spectrum` and reversed poling as a negative
+  /// `poling_period_um`, otherwise this is the spdcalc config.
+  /// Values are rounded for readability unless `exact` is set.
+  pub(crate) fn config_value(&self, exact: bool) -> Value {
+    let config = if exact {
+      self.exact_config()
+    } else {
+      SPDCConfig::from(self.0.clone())
+    };
+    let mut value = serde_yaml::to_value(config).expect("configs always serialize");
+    let round = |v: f64| {
+      if exact {
+        v
+      } else {
+        sigfigs(v, SIG_FIGS_IN_CONFIG)
+      }
+    };
+    let waists = [
+      self.0.pump.waist(),
+      self.0.signal.waist(),
+      self.0.idler.waist(),
+    ];
+    for (beam, waist) in BEAMS.iter().zip(waists) {
+      if waist.x == waist.y {
+        continue;
+      }
+      if let Some(section) = section(&mut value, beam) {
+        if let Some(x) = section.get("waist_um").cloned() {
+          let y = round(*(waist.y / (MICRO * M)));
+          section.insert("waist_um".into(), vec![x, y.into()].into());
+        }
+      }
+    }
+    if self.poling_is_reversed() {
+      if let Some(pp) = section(&mut value, "periodic_poling") {
+        if let Some(period) = pp.get("poling_period_um").and_then(Value::as_f64) {
+          pp.insert("poling_period_um".into(), (-period).into());
+        }
+      }
+    }
+    if !self.1.is_gaussian() {
+      if let Some(pump) = section(&mut value, "pump") {
+        pump.insert(
+          "spectrum".into(),
+          serde_yaml::to_value(&self.1).expect("pump spectra always serialize"),
+        );
+      }
+    }
+    value
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  const ELLIPTICAL: &str = "
+crystal:
+  kind: KTP
+  pm_type: e->eo
+  phi_deg: 0
+  theta_deg: 90
+  length_um: 14000
+  temperature_c: 20
+pump:
+  wavelength_nm: 775
+  waist_um: [200, 50]
+  bandwidth_nm: 0.5
+  average_power_mw: 300
+signal:
+  wavelength_nm: 1550
+  phi_deg: 0
+  theta_external_deg: 0
+  waist_um: [100, 80]
+  waist_position_um: auto
+idler: auto
+periodic_poling:
+  poling_period_um: auto
+deff_pm_per_volt: 7.6
+";
+
+  fn waist_um(waist: BeamWaist) -> (f64, f64) {
+    (*(waist.x / (MICRO * M)), *(waist.y / (MICRO * M)))
+  }
+
+  #[test]
+  fn elliptical_waists_are_parsed() {
+    let spdc = parse_config(ELLIPTICAL, Format::Yaml).unwrap();
+    assert_eq!(waist_um(spdc.0.pump.waist()), (200., 50.));
+    assert_eq!(waist_um(spdc.0.signal.waist()), (100., 80.));
+    // the automatic idler follows the signal
+    assert_eq!(waist_um(spdc.0.idler.waist()), (100., 80.));
+  }
+
+  #[test]
+  fn elliptical_waists_round_trip() {
+    let spdc = parse_config(ELLIPTICAL, Format::Yaml).unwrap();
+    let yaml = serde_yaml::to_string(&spdc.config_value(false)).unwrap();
+    let json = serde_json::to_string(&spdc.config_value(true)).unwrap();
+    for copy in [
+      parse_config(&yaml, Format::Yaml).unwrap(),
+      parse_config(&json, Format::Json).unwrap(),
+    ] {
+      assert_eq!(waist_um(copy.0.pump.waist()), (200., 50.));
+      assert_eq!(waist_um(copy.0.signal.waist()), (100., 80.));
+      assert_eq!(waist_um(copy.0.idler.waist()), (100., 80.));
+    }
+  }
+
+  #[test]
+  fn toml_configs_match_yaml() {
+    let toml = r#"
+idler = "auto"
+deff_pm_per_volt = 7.6
+
+[crystal]
+kind = "KTP"
+pm_type = "e->eo"
+phi_deg = 0
+theta_deg = 90
+length_um = 14000
+temperature_c = 20
+
+[pump]
+wavelength_nm = 775
+waist_um = [200, 50]
+bandwidth_nm = 0.5
+average_power_mw = 300
+
+[signal]
+wavelength_nm = 1550
+phi_deg = 0
+theta_external_deg = 0
+waist_um = [100, 80]
+waist_position_um = "auto"
+
+[periodic_poling]
+poling_period_um = "auto"
+"#;
+    let from_toml = parse_config(toml, Format::Toml).unwrap();
+    let from_yaml = parse_config(ELLIPTICAL, Format::Yaml).unwrap();
+    assert_eq!(from_toml.config_value(true), from_yaml.config_value(true));
+    let written = toml::to_string(&without_nulls(from_yaml.config_value(false))).unwrap();
+    let copy = parse_config(&written, Format::Toml).unwrap();
+    assert_eq!(waist_um(copy.0.pump.waist()), (200., 50.));
+    assert_eq!(waist_um(copy.0.idler.waist()), (100., 80.));
+  }
+
+  #[test]
+  fn circular_waists_are_unchanged() {
+    let spdc = SPDC::default();
+    let value = spdc.config_value(false);
+    assert!(value["pump"]["waist_um"].is_f64());
+    assert!(value["signal"]["waist_um"].is_f64());
+  }
+
+  #[test]
+  fn malformed_waists_are_rejected() {
+    let yaml = ELLIPTICAL.replace("[200, 50]", "[200, 50, 10]");
+    let err = parse_config(&yaml, Format::Yaml).unwrap_err();
+    assert_eq!(err.path.as_deref(), Some("pump.waist_um"));
+  }
+
+  #[test]
+  fn errors_have_paths_and_locations() {
+    let yaml = ELLIPTICAL
+      .replace("[200, 50]", "200")
+      .replace("[100, 80]", "100")
+      .replace("length_um: 14000", "length_um: oops");
+    let err = parse_config(&yaml, Format::Yaml).unwrap_err();
+    assert_eq!(err.path.as_deref(), Some("crystal.length_um"));
+    assert_eq!((err.line, err.column), (Some(7), Some(14)));
+    let toml = "[crystal]\nkind = \"KTP\"\nlength_um = \"oops\"\n";
+    let err = parse_config(toml, Format::Toml).unwrap_err();
+    assert_eq!(err.path.as_deref(), Some("crystal.length_um"));
+    assert_eq!((err.line, err.column), (Some(3), Some(13)));
+    let json = r#"{"crystal": {"kind": "KTP", "length_um": "oops"}}"#;
+    let err = parse_config(json, Format::Json).unwrap_err();
+    assert_eq!(err.path.as_deref(), Some("crystal.length_um"));
+    assert_eq!(err.line, Some(1));
+  }
+}
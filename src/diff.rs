@@ -0,0 +1,212 @@
+use crate::config::AutoFields;
+use crate::spdc::json_is_close;
+use serde_json::{json, Map, Value};
+
+/// Units of config fields, by the suffix of their name
+const UNITS: [(&str, &str); 6] = [
+  ("_nm", "nm"),
+  ("_um", "um"),
+  ("_deg", "deg"),
+  ("_c", "°C"),
+  ("_mw", "mW"),
+  ("_pm_per_volt", "pm/V"),
+];
+
+/// The unit of the value at a dotted config path, if it has one
+fn unit_of(path: &str) -> Option<&'static str> {
+  let field = path.rsplit('.').next()?;
+  UNITS
+    .iter()
+    .find(|(suffix, _)| field.ends_with(suffix))
+    .map(|(_, unit)| *unit)
+}
+
+/// A value that differs between two configs
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Change {
+  pub(crate) path: String,
+  /// The old value, or null if the field is missing
+  pub(crate) old: Value,
+  /// The new value, or null if the field is missing
+  pub(crate) new: Value,
+}
+
+impl Change {
+  pub(crate) fn to_json(&self) -> Value {
+    json!({
+      "path": self.path,
+      "old": self.old,
+      "new": self.new,
+      "unit": unit_of(&self.path),
+    })
+  }
+}
+
+/// Collect the differences between two configs into `changes`
+fn diff_into(
+  old: &Value,
+  new: &Value,
+  path: &str,
+  rtol: f64,
+  atol: f64,
+  changes: &mut Vec<Change>,
+) {
+  let join = |key: &str| {
+    if path.is_empty() {
+      key.to_string()
+    } else {
+      format!("{}.{}", path, key)
+    }
+  };
+  match (old, new) {
+    (Value::Object(old), Value::Object(new)) => {
+      for (key, value) in old {
+        let other = new.get(key).unwrap_or(&Value::Null);
+        diff_into(value, other, &join(key), rtol, atol, changes);
+      }
+      for (key, value) in new.iter().filter(|(key, _)| !old.contains_key(*key)) {
+        diff_into(&Value::Null, value, &join(key), rtol, atol, changes);
+      }
+    }
+    (old, new) => {
+      if !json_is_close(old, new, rtol, atol) {
+        changes.push(Change {
+          path: path.to_string(),
+          old: old.clone(),
+          new: new.clone(),
+        });
+      }
+    }
+  }
+}
+
+/// The values that differ between two configs, ordered by field name
+///
+/// Sections are compared field by field, anything else (including lists like
+/// elliptical waists) as a whole. Numbers are compared within a tolerance, as in
+/// `SPDC.is_close`. A section that is null or missing in one config (like
+/// `periodic_poling`) is a single change.
+pub(crate) fn diff(old: &Value, new: &Value, rtol: f64, atol: f64) -> Vec<Change> {
+  let mut changes = Vec::new();
+  diff_into(old, new, "", rtol, atol, &mut changes);
+  changes
+}
+
+/// Merge a partial config into a config
+///
+/// Sections are merged field by field, anything else replaces the value.
+pub(crate) fn merge(config: &mut Value, patch: Value) {
+  match (config, patch) {
+    (Value::Object(config), Value::Object(patch)) => {
+      for (key, value) in patch {
+        match config.get_mut(&key) {
+          Some(existing) => merge(existing, value),
+          None => {
+            config.insert(key, value);
+          }
+        }
+      }
+    }
+    (config, patch) => *config = patch,
+  }
+}
+
+/// Whether a partial config sets the value at a dotted path, or a value within it
+fn sets(patch: &Map<String, Value>, path: &str) -> bool {
+  let mut value = patch;
+  let mut keys = path.split('.').peekable();
+  while let Some(key) = keys.next() {
+    match (value.get(key), keys.peek()) {
+      (None, _) => return false,
+      (Some(_), None) => return true,
+      (Some(Value::Object(section)), Some(_)) => value = section,
+      // the whole section is replaced
+      (Some(_), Some(_)) => return true,
+    }
+  }
+  false
+}
+
+impl AutoFields {
+  /// The auto fields that a partial config does not set
+  pub(crate) fn unless_set_by(mut self, patch: &Map<String, Value>) -> Self {
+    if sets(patch, "crystal.theta_deg") {
+      self.crystal_theta = false;
+    }
+    if sets(patch, "periodic_poling.poling_period_um") {
+      self.poling_period = false;
+    }
+    if sets(patch, "signal.waist_position_um") {
+      self.signal_waist_position = false;
+    }
+    if sets(patch, "idler") {
+      self.set_explicit_idler();
+    }
+    if sets(patch, "idler.waist_position_um") {
+      self.idler_waist_position = false;
+    }
+    self
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn paths(changes: &[Change]) -> Vec<&str> {
+    changes.iter().map(|c| c.path.as_str()).collect()
+  }
+
+  #[test]
+  fn changed_values_are_found_by_path() {
+    let old = json!({
+      "crystal": { "length_um": 2000.0, "theta_deg": 90.0 },
+      "pump": { "waist_um": 100.0 },
+      "periodic_poling": null,
+    });
+    let new = json!({
+      "crystal": { "length_um": 2000.0 * (1. + 1e-12), "theta_deg": 85.0 },
+      "pump": { "waist_um": [100.0, 50.0] },
+      "periodic_poling": { "poling_period_um": 46.5 },
+    });
+    let changes = diff(&old, &new, 1e-9, 0.);
+    assert_eq!(
+      paths(&changes),
+      vec!["crystal.theta_deg", "periodic_poling", "pump.waist_um"]
+    );
+    assert_eq!(changes[1].old, Value::Null);
+    assert_eq!(changes[1].new, json!({ "poling_period_um": 46.5 }));
+    assert_eq!(paths(&diff(&new, &old, 1e-9, 0.)), paths(&changes));
+    assert!(diff(&old, &old, 1e-9, 0.).is_empty());
+  }
+
+  #[test]
+  fn units_follow_field_names() {
+    assert_eq!(unit_of("pump.waist_um"), Some("um"));
+    assert_eq!(unit_of("crystal.temperature_c"), Some("°C"));
+    assert_eq!(unit_of("deff_pm_per_volt"), Some("pm/V"));
+    assert_eq!(unit_of("pump.spectrum_threshold"), None);
+    assert_eq!(unit_of("crystal.kind"), None);
+  }
+
+  #[test]
+  fn patches_merge_sections() {
+    let mut config = json!({
+      "pump": { "waist_um": 100.0, "bandwidth_nm": 0.5 },
+      "periodic_poling": { "poling_period_um": 46.5 },
+    });
+    merge(
+      &mut config,
+      json!({ "pump": { "waist_um": 80 }, "periodic_poling": null }),
+    );
+    assert_eq!(
+      config,
+      json!({ "pump": { "waist_um": 80, "bandwidth_nm": 0.5 }, "periodic_poling": null })
+    );
+    let patch = json!({ "idler": { "waist_um": 80 }, "crystal": "KTP" });
+    let patch = patch.as_object().unwrap();
+    assert!(sets(patch, "idler"));
+    assert!(!sets(patch, "idler.waist_position_um"));
+    assert!(sets(patch, "crystal.theta_deg"));
+  }
+}
@@ -1,8 +1,167 @@
 use crate::*;
-use pyo3::exceptions::PyRuntimeError;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::sync::GILOnceCell;
+use pyo3::types::{PyDict, PyTuple, PyType};
+
+create_exception!(
+  spdcalc,
+  SpdcError,
+  PyRuntimeError,
+  "Base class of the errors raised by spdcalc"
+);
+
+const CONFIG_ERROR_DOC: &str = "An SPDC config that can not be parsed or describes an invalid setup
+
+This is also a ``ValueError``, as which configs were rejected before.
+
+Attributes
+----------
+path : str or None
+    The dotted path of the offending value (e.g. ``\"crystal.length_um\"``), if known
+line : int or None
+    The line of the offending value in the config text, if known
+column : int or None
+    The column of the offending value in the config text, if known";
+
+static CONFIG_ERROR: GILOnceCell<Py<PyType>> = GILOnceCell::new();
+
+/// The `ConfigError` class, a subclass of both `SpdcError` and `ValueError`
+///
+/// `create_exception!` only supports a single base class, so the class is
+/// created by calling `type`.
+fn config_error_type(py: Python<'_>) -> &Bound<'_, PyType> {
+  CONFIG_ERROR
+    .get_or_init(py, || {
+      let bases = PyTuple::new_bound(
+        py,
+        [
+          py.get_type_bound::<SpdcError>(),
+          py.get_type_bound::<PyValueError>(),
+        ],
+      );
+      let dict = PyDict::new_bound(py);
+      dict.set_item("__module__", "spdcalc").unwrap();
+      dict.set_item("__doc__", CONFIG_ERROR_DOC).unwrap();
+      py.get_type_bound::<PyType>()
+        .call1(("ConfigError", bases, dict))
+        .and_then(|t| Ok(t.downcast_into::<PyType>()?))
+        .expect("Failed to initialize new exception type.")
+        .unbind()
+    })
+    .bind(py)
+}
+create_exception!(
+  spdcalc,
+  PhasematchingError,
+  SpdcError,
+  "The setup can not be phasematched, e.g. no poling period or temperature satisfies it"
+);
+create_exception!(
+  spdcalc,
+  IntegrationError,
+  SpdcError,
+  "A numerical calculation over a spectrum failed, e.g. a decomposition did not converge"
+);
+create_exception!(
+  spdcalc,
+  UnitError,
+  SpdcError,
+  "A value was given in the wrong units or kind of space"
+);
+
+/// Messages of spdcalc errors by the exception they are raised as
+///
+/// spdcalc errors only carry a message, so they are classified by its start.
+/// Errors of these bindings are raised as their kind directly (see `PyKindError`).
+const KINDS: &[(&str, ErrorKind)] = &[
+  ("Must specify one of", ErrorKind::Config),
+  ("Can not autocalc", ErrorKind::Config),
+  ("PMType", ErrorKind::Config),
+  ("Can not parse", ErrorKind::Config),
+  (
+    "Could not determine poling period",
+    ErrorKind::Phasematching,
+  ),
+  (
+    "Signal wavelength must be greater",
+    ErrorKind::Phasematching,
+  ),
+  ("SVD did not converge", ErrorKind::Integration),
+  ("Spectrum provided is not square", ErrorKind::Integration),
+];
+
+/// The python exception class of an error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ErrorKind {
+  Spdc,
+  Config,
+  Phasematching,
+  Integration,
+  /// An invalid argument, raised as `ValueError`
+  Value,
+}
+
+impl ErrorKind {
+  /// Create a python exception of this kind
+  pub(crate) fn new_err(self, message: String) -> PyErr {
+    match self {
+      ErrorKind::Spdc => SpdcError::new_err(message),
+      ErrorKind::Config => PyConfigError::new(message).into(),
+      ErrorKind::Phasematching => PhasematchingError::new_err(message),
+      ErrorKind::Integration => IntegrationError::new_err(message),
+      ErrorKind::Value => PyValueError::new_err(message),
+    }
+  }
+
+  /// An error of this kind, to raise from rust code that can not return a `PyErr`
+  pub(crate) fn err(self, message: impl Into<String>) -> PyKindError {
+    PyKindError {
+      kind: self,
+      message: message.into(),
+    }
+  }
+}
+
+/// An error of these bindings, raised as the exception class of its kind
+///
+/// spdcalc errors within calculations are converted into this, classified by
+/// their message.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PyKindError {
+  pub(crate) kind: ErrorKind,
+  pub(crate) message: String,
+}
+
+impl From<::spdcalc::SPDCError> for PyKindError {
+  fn from(err: ::spdcalc::SPDCError) -> Self {
+    PySpdcError(err).into()
+  }
+}
+
+impl From<PySpdcError> for PyKindError {
+  fn from(err: PySpdcError) -> Self {
+    err.kind().err(err.0 .0)
+  }
+}
+
+impl From<PyKindError> for PyErr {
+  fn from(err: PyKindError) -> Self {
+    err.kind.new_err(err.message)
+  }
+}
 
 pub(crate) struct PySpdcError(pub(crate) ::spdcalc::SPDCError);
 
+impl PySpdcError {
+  pub(crate) fn kind(&self) -> ErrorKind {
+    KINDS
+      .iter()
+      .find(|(start, _)| self.0 .0.starts_with(start))
+      .map_or(ErrorKind::Spdc, |(_, kind)| *kind)
+  }
+}
+
 impl From<::spdcalc::SPDCError> for PySpdcError {
   fn from(err: ::spdcalc::SPDCError) -> Self {
     PySpdcError(err)
@@ -11,7 +170,7 @@ impl From<::spdcalc::SPDCError> for PySpdcError {
 
 impl From<PySpdcError> for PyErr {
   fn from(err: PySpdcError) -> Self {
-    PyRuntimeError::new_err(err.0.to_string())
+    err.kind().new_err(err.0.to_string())
   }
 }
 
@@ -20,3 +179,160 @@ impl From<serde_json::Error> for PySpdcError {
     PySpdcError(::spdcalc::SPDCError(err.to_string()))
   }
 }
+
+/// An invalid config, with the location of the offending value when known
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct PyConfigError {
+  pub(crate) message: String,
+  pub(crate) path: Option<String>,
+  pub(crate) line: Option<usize>,
+  pub(crate) column: Option<usize>,
+}
+
+impl PyConfigError {
+  pub(crate) fn new(message: String) -> Self {
+    Self {
+      message,
+      ..Self::default()
+    }
+  }
+
+  /// An error of the value at a dotted config path
+  pub(crate) fn at(path: &str, message: impl std::fmt::Display) -> Self {
+    Self {
+      message: format!("{}: {}", path, message),
+      path: Some(path.to_string()),
+      ..Self::default()
+    }
+  }
+
+  /// Record the path of a deserialization error, unless it is the root
+  fn with_path(mut self, path: &serde_path_to_error::Path) -> Self {
+    let path = path.to_string();
+    if path != "." {
+      if !self.message.starts_with(&path) {
+        self.message = format!("{}: {}", path, self.message);
+      }
+      self.path = Some(path);
+    }
+    self
+  }
+
+  pub(crate) fn from_yaml_path(err: serde_path_to_error::Error<serde_yaml::Error>) -> Self {
+    let path = err.path().clone();
+    Self::from(err.into_inner()).with_path(&path)
+  }
+
+  pub(crate) fn from_json_path(err: serde_path_to_error::Error<serde_json::Error>) -> Self {
+    let path = err.path().clone();
+    Self::from(err.into_inner()).with_path(&path)
+  }
+
+  /// A TOML error, located in the text it came from
+  ///
+  /// TOML errors only know the byte span of the offending value.
+  pub(crate) fn from_toml(err: toml::de::Error, text: &str) -> Self {
+    let location = err.span().map(|span| {
+      let before = &text[..span.start.min(text.len())];
+      let line = before.matches('\n').count() + 1;
+      let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+      (line, column)
+    });
+    Self {
+      // the message of the error itself, without the excerpt of the text
+      message: err.message().to_string(),
+      line: location.map(|(line, _)| line),
+      column: location.map(|(_, column)| column),
+      ..Self::default()
+    }
+  }
+
+  pub(crate) fn from_toml_path(
+    err: serde_path_to_error::Error<toml::de::Error>,
+    text: &str,
+  ) -> Self {
+    let path = err.path().clone();
+    Self::from_toml(err.into_inner(), text).with_path(&path)
+  }
+}
+
+impl From<serde_yaml::Error> for PyConfigError {
+  fn from(err: serde_yaml::Error) -> Self {
+    let location = err.location();
+    Self {
+      message: err.to_string(),
+      line: location.as_ref().map(|l| l.line()),
+      column: location.as_ref().map(|l| l.column()),
+      ..Self::default()
+    }
+  }
+}
+
+impl From<serde_json::Error> for PyConfigError {
+  fn from(err: serde_json::Error) -> Self {
+    // serde_json reports line 0 when the error has no location
+    let located = err.line() > 0;
+    Self {
+      message: err.to_string(),
+      line: located.then(|| err.line()),
+      column: located.then(|| err.column()),
+      ..Self::default()
+    }
+  }
+}
+
+impl From<PyConfigError> for PyErr {
+  fn from(err: PyConfigError) -> Self {
+    Python::with_gil(|py| {
+      let exception = PyErr::from_type_bound(config_error_type(py).clone(), err.message);
+      let value = exception.value_bound(py);
+      let fields = [
+        ("path", err.path.into_py(py)),
+        ("line", err.line.into_py(py)),
+        ("column", err.column.into_py(py)),
+      ];
+      for (name, field) in fields {
+        if let Err(e) = value.setattr(name, field) {
+          return e;
+        }
+      }
+      exception
+    })
+  }
+}
+
+/// Register the exception classes with the python module
+pub(crate) fn add_exceptions(m: &Bound<'_, PyModule>) -> PyResult<()> {
+  let py = m.py();
+  m.add("SpdcError", py.get_type_bound::<SpdcError>())?;
+  m.add("ConfigError", config_error_type(py))?;
+  m.add(
+    "PhasematchingError",
+    py.get_type_bound::<PhasematchingError>(),
+  )?;
+  m.add("IntegrationError", py.get_type_bound::<IntegrationError>())?;
+  m.add("UnitError", py.get_type_bound::<UnitError>())?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn spdcalc_errors_are_classified() {
+    let kind = |message: &str| PySpdcError(::spdcalc::SPDCError(message.into())).kind();
+    assert_eq!(
+      kind("Could not determine poling period from specified values"),
+      ErrorKind::Phasematching
+    );
+    assert_eq!(kind("PMType x is not defined"), ErrorKind::Config);
+    assert_eq!(
+      kind("SVD did not converge while calculating schmidt number"),
+      ErrorKind::Integration
+    );
+    assert_eq!(kind("Something else"), ErrorKind::Spdc);
+    let err = PyKindError::from(::spdcalc::SPDCError("Can not autocalc theta".into()));
+    assert_eq!(err.kind, ErrorKind::Config);
+  }
+}
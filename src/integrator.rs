@@ -1,4 +1,5 @@
 use super::*;
+use pyo3::types::{PyDict, PyTuple, PyType};
 
 /// Integrator for numerical integration
 ///
@@ -11,7 +12,7 @@ use super::*;
 /// - Gauss-Konrod quadrature
 /// - Gauss-Legendre quadrature
 /// - Clenshaw-Curtis quadrature
-#[pyclass]
+#[pyclass(module = "spdcalc")]
 #[derive(Debug, Clone)]
 pub(crate) struct Integrator(pub(crate) ::spdcalc::math::Integrator);
 
@@ -21,6 +22,14 @@ impl Integrator {
     format!("{}", serde_yaml::to_string(&self.0).unwrap())
   }
 
+  /// Create the default integrator
+  ///
+  /// Equivalent to `Integrator.default()`
+  #[new]
+  pub fn new() -> Self {
+    <Self as Default>::default()
+  }
+
   /// Default integrator
   ///
   /// The default is `Simpson(divs=50)`.
@@ -29,6 +38,39 @@ impl Integrator {
     <Self as Default>::default()
   }
 
+  // pickle support. The state is the JSON serialization of the method.
+  fn __getstate__(&self) -> Result<String, PySpdcError> {
+    Ok(serde_json::to_string(&self.0)?)
+  }
+
+  fn __setstate__(&mut self, state: &str) -> Result<(), PyConfigError> {
+    self.0 = serde_json::from_str(state)?;
+    Ok(())
+  }
+
+  fn __reduce__<'py>(
+    slf: &Bound<'py, Self>,
+  ) -> PyResult<(Bound<'py, PyType>, Bound<'py, PyTuple>, String)> {
+    let args = PyTuple::empty_bound(slf.py());
+    Ok((slf.get_type(), args, slf.borrow().__getstate__()?))
+  }
+
+  fn __copy__(&self) -> Self {
+    self.clone()
+  }
+
+  fn __deepcopy__(&self, _memo: &Bound<'_, PyDict>) -> Self {
+    self.clone()
+  }
+
+  fn __eq__(&self, other: &Self) -> Result<bool, PySpdcError> {
+    Ok(serde_json::to_value(self.0)? == serde_json::to_value(other.0)?)
+  }
+
+  fn __hash__(&self) -> Result<u64, PySpdcError> {
+    Ok(hash_of(&self.__getstate__()?))
+  }
+
   /// Simpson's rule
   ///
   /// Parameters
@@ -1,7 +1,12 @@
 use super::*;
-use pyo3::exceptions::PyRuntimeError;
+use crate::math::{centroid, fwhm};
+use numpy::{PyArray1, PyArrayDyn};
+use pyo3::exceptions::PyValueError;
+use pyo3::types::{PyDict, PyType};
+use spdcalc::dim::f64prefixes::NANO;
 use spdcalc::dim::ucum::*;
-use spdcalc::Complex;
+use spdcalc::utils::vacuum_wavelength_to_frequency;
+use spdcalc::{Complex, Frequency};
 
 /// Represents the joint spectrum of an SPDC process
 ///
@@ -10,9 +15,14 @@ use spdcalc::Complex;
 ///
 /// NOTE: The easiest way to create this is to use the :func:`SPDC.joint_spectrum` method
 /// on a :class:`SPDC` object.
-#[pyclass]
+#[pyclass(module = "spdcalc")]
 #[derive(Debug, Clone)]
-pub(crate) struct JointSpectrum(pub(crate) ::spdcalc::JointSpectrum);
+pub(crate) struct JointSpectrum(
+  pub(crate) crate::pump::Spectrum,
+  // the setup this spectrum was created from, kept for pickling
+  SPDC,
+  Integrator,
+);
 
 #[pymethods]
 impl JointSpectrum {
@@ -31,24 +41,45 @@ impl JointSpectrum {
   ///     The new JointSpectrum instance
   #[new]
   pub fn new(spdc: SPDC, integrator: Integrator) -> PyResult<Self> {
-    Ok(Self(::spdcalc::JointSpectrum::new(spdc.0, integrator.0)))
+    Ok(Self::from_setup(spdc, integrator))
+  }
+
+  // pickle support. Reconstructed from the setup it was created from.
+  fn __reduce__<'py>(slf: &Bound<'py, Self>) -> PyResult<(Bound<'py, PyType>, (SPDC, Integrator))> {
+    let js = slf.borrow();
+    Ok((slf.get_type(), (js.1.clone(), js.2.clone())))
+  }
+
+  fn __copy__(&self) -> Self {
+    self.clone()
+  }
+
+  fn __deepcopy__(&self, _memo: &Bound<'_, PyDict>) -> Self {
+    self.clone()
   }
 
   /// Calculate the joint spectral amplitude (JSA) at a specific signal and idler frequency
   ///
   /// Parameters
   /// ----------
-  /// omega_s_hz : float
-  ///     Signal frequency in Hz
-  /// omega_i_hz : float
-  ///     Idler frequency in Hz
+  /// omega_s_rad_per_s : float or numpy.ndarray
+  ///     Signal angular frequency in radians per second
+  /// omega_i_rad_per_s : float or numpy.ndarray
+  ///     Idler angular frequency in radians per second
   ///
   /// Returns
   /// -------
-  /// `complex`
-  ///     The JSA value
-  pub fn jsa(&self, omega_s_hz: f64, omega_i_hz: f64) -> Complex<f64> {
-    self.0.jsa(omega_s_hz * RAD * HZ, omega_i_hz * RAD * HZ)
+  /// `complex` or `numpy.ndarray` of `complex`
+  ///     The JSA value, or an array of values broadcast from the inputs
+  pub fn jsa(
+    &self,
+    py: Python<'_>,
+    omega_s_rad_per_s: FloatOrArray<'_>,
+    omega_i_rad_per_s: FloatOrArray<'_>,
+  ) -> PyResult<PyObject> {
+    par_map_broadcast(py, &omega_s_rad_per_s, &omega_i_rad_per_s, |ws, wi| {
+      self.0.jsa(ws * RAD * HZ, wi * RAD * HZ)
+    })
   }
 
   /// Calculate the JSA over a range of frequencies
@@ -60,29 +91,40 @@ impl JointSpectrum {
   ///
   /// Returns
   /// -------
-  /// `list` of `complex`
-  ///     Vector of JSA values
-  pub fn jsa_range(&self, si_range: SIRange) -> Vec<Complex<f64>> {
-    self.0.jsa_range(si_range)
+  /// `numpy.ndarray` of `complex`
+  ///     Array of JSA values, shaped like the range
+  pub fn jsa_range<'py>(
+    &self,
+    py: Python<'py>,
+    si_range: SIRange,
+  ) -> PyResult<Bound<'py, PyArrayDyn<Complex<f64>>>> {
+    let shape = si_range.shape();
+    let values = py.allow_threads(|| self.0.jsa_range(si_range));
+    to_shaped_pyarray(py, values, shape)
   }
 
   /// Calculate the normalized JSA at specific frequencies
   ///
   /// Parameters
   /// ----------
-  /// omega_s_hz : float
-  ///     Signal frequency in Hz
-  /// omega_i_hz : float
-  ///     Idler frequency in Hz
+  /// omega_s_rad_per_s : float or numpy.ndarray
+  ///     Signal angular frequency in radians per second
+  /// omega_i_rad_per_s : float or numpy.ndarray
+  ///     Idler angular frequency in radians per second
   ///
   /// Returns
   /// -------
-  /// `complex`
-  ///     The normalized JSA value
-  pub fn jsa_normalized(&self, omega_s_hz: f64, omega_i_hz: f64) -> Complex<f64> {
-    self
-      .0
-      .jsa_normalized(omega_s_hz * RAD * HZ, omega_i_hz * RAD * HZ)
+  /// `complex` or `numpy.ndarray` of `complex`
+  ///     The normalized JSA value, or an array of values broadcast from the inputs
+  pub fn jsa_normalized(
+    &self,
+    py: Python<'_>,
+    omega_s_rad_per_s: FloatOrArray<'_>,
+    omega_i_rad_per_s: FloatOrArray<'_>,
+  ) -> PyResult<PyObject> {
+    par_map_broadcast(py, &omega_s_rad_per_s, &omega_i_rad_per_s, |ws, wi| {
+      self.0.jsa_normalized(ws * RAD * HZ, wi * RAD * HZ)
+    })
   }
 
   /// Calculate the normalized JSA over a range of frequencies
@@ -94,27 +136,40 @@ impl JointSpectrum {
   ///
   /// Returns
   /// -------
-  /// `list` of `complex`
-  ///     Vector of normalized JSA values
-  pub fn jsa_normalized_range(&self, si_range: SIRange) -> Vec<Complex<f64>> {
-    self.0.jsa_normalized_range(si_range)
+  /// `numpy.ndarray` of `complex`
+  ///     Array of normalized JSA values, shaped like the range
+  pub fn jsa_normalized_range<'py>(
+    &self,
+    py: Python<'py>,
+    si_range: SIRange,
+  ) -> PyResult<Bound<'py, PyArrayDyn<Complex<f64>>>> {
+    let shape = si_range.shape();
+    let values = py.allow_threads(|| self.0.jsa_normalized_range(si_range));
+    to_shaped_pyarray(py, values, shape)
   }
 
   /// Calculate the joint spectral intensity (JSI) at specific frequencies
   ///
   /// Parameters
   /// ----------
-  /// omega_s_hz : float
-  ///     Signal frequency in Hz
-  /// omega_i_hz : float
-  ///     Idler frequency in Hz
+  /// omega_s_rad_per_s : float or numpy.ndarray
+  ///     Signal angular frequency in radians per second
+  /// omega_i_rad_per_s : float or numpy.ndarray
+  ///     Idler angular frequency in radians per second
   ///
   /// Returns
   /// -------
-  /// `float`
-  ///     The JSI value
-  pub fn jsi(&self, omega_s_hz: f64, omega_i_hz: f64) -> f64 {
-    *(self.0.jsi(omega_s_hz * RAD * HZ, omega_i_hz * RAD * HZ) / spdcalc::JSIUnits::new(1.))
+  /// `float` or `numpy.ndarray` of `float`
+  ///     The JSI value, or an array of values broadcast from the inputs
+  pub fn jsi(
+    &self,
+    py: Python<'_>,
+    omega_s_rad_per_s: FloatOrArray<'_>,
+    omega_i_rad_per_s: FloatOrArray<'_>,
+  ) -> PyResult<PyObject> {
+    par_map_broadcast(py, &omega_s_rad_per_s, &omega_i_rad_per_s, |ws, wi| {
+      *(self.0.jsi(ws * RAD * HZ, wi * RAD * HZ) / spdcalc::JSIUnits::new(1.))
+    })
   }
 
   /// Calculate the JSI over a range of frequencies
@@ -126,34 +181,47 @@ impl JointSpectrum {
   ///
   /// Returns
   /// -------
-  /// `list` of `float`
-  ///     Vector of JSI values
-  pub fn jsi_range(&self, si_range: SIRange) -> Vec<f64> {
-    self
-      .0
-      .jsi_range(si_range)
-      .into_iter()
-      .map(|jsi| *(jsi / spdcalc::JSIUnits::new(1.)))
-      .collect()
+  /// `numpy.ndarray` of `float`
+  ///     Array of JSI values, shaped like the range
+  pub fn jsi_range<'py>(
+    &self,
+    py: Python<'py>,
+    si_range: SIRange,
+  ) -> PyResult<Bound<'py, PyArrayDyn<f64>>> {
+    let shape = si_range.shape();
+    let jsi = py.allow_threads(|| {
+      self
+        .0
+        .jsi_range(si_range)
+        .into_iter()
+        .map(|jsi| *(jsi / spdcalc::JSIUnits::new(1.)))
+        .collect()
+    });
+    to_shaped_pyarray(py, jsi, shape)
   }
 
   /// Calculate the normalized JSI at specific frequencies
   ///
   /// Parameters
   /// ----------
-  /// omega_s_hz : float
-  ///     Signal frequency in Hz
-  /// omega_i_hz : float
-  ///     Idler frequency in Hz
+  /// omega_s_rad_per_s : float or numpy.ndarray
+  ///     Signal angular frequency in radians per second
+  /// omega_i_rad_per_s : float or numpy.ndarray
+  ///     Idler angular frequency in radians per second
   ///
   /// Returns
   /// -------
-  /// `float`
-  ///     The normalized JSI value
-  pub fn jsi_normalized(&self, omega_s_hz: f64, omega_i_hz: f64) -> f64 {
-    self
-      .0
-      .jsi_normalized(omega_s_hz * RAD * HZ, omega_i_hz * RAD * HZ)
+  /// `float` or `numpy.ndarray` of `float`
+  ///     The normalized JSI value, or an array of values broadcast from the inputs
+  pub fn jsi_normalized(
+    &self,
+    py: Python<'_>,
+    omega_s_rad_per_s: FloatOrArray<'_>,
+    omega_i_rad_per_s: FloatOrArray<'_>,
+  ) -> PyResult<PyObject> {
+    par_map_broadcast(py, &omega_s_rad_per_s, &omega_i_rad_per_s, |ws, wi| {
+      self.0.jsi_normalized(ws * RAD * HZ, wi * RAD * HZ)
+    })
   }
 
   /// Calculate the normalized JSI over a range of frequencies
@@ -165,30 +233,40 @@ impl JointSpectrum {
   ///
   /// Returns
   /// -------
-  /// `list` of `float`
-  ///     Vector of normalized JSI values
-  pub fn jsi_normalized_range(&self, si_range: SIRange) -> Vec<f64> {
-    self.0.jsi_normalized_range(si_range)
+  /// `numpy.ndarray` of `float`
+  ///     Array of normalized JSI values, shaped like the range
+  pub fn jsi_normalized_range<'py>(
+    &self,
+    py: Python<'py>,
+    si_range: SIRange,
+  ) -> PyResult<Bound<'py, PyArrayDyn<f64>>> {
+    let shape = si_range.shape();
+    let values = py.allow_threads(|| self.0.jsi_normalized_range(si_range));
+    to_shaped_pyarray(py, values, shape)
   }
 
   /// Calculate the singles JSI at specific frequencies
   ///
   /// Parameters
   /// ----------
-  /// omega_s_hz : float
-  ///     Signal frequency in Hz
-  /// omega_i_hz : float
-  ///     Idler frequency in Hz
+  /// omega_s_rad_per_s : float or numpy.ndarray
+  ///     Signal angular frequency in radians per second
+  /// omega_i_rad_per_s : float or numpy.ndarray
+  ///     Idler angular frequency in radians per second
   ///
   /// Returns
   /// -------
-  /// `float`
-  ///     The singles JSI value
-  pub fn jsi_singles(&self, omega_s_hz: f64, omega_i_hz: f64) -> f64 {
-    *(self
-      .0
-      .jsi_singles(omega_s_hz * RAD * HZ, omega_i_hz * RAD * HZ)
-      / spdcalc::JSIUnits::new(1.))
+  /// `float` or `numpy.ndarray` of `float`
+  ///     The singles JSI value, or an array of values broadcast from the inputs
+  pub fn jsi_singles(
+    &self,
+    py: Python<'_>,
+    omega_s_rad_per_s: FloatOrArray<'_>,
+    omega_i_rad_per_s: FloatOrArray<'_>,
+  ) -> PyResult<PyObject> {
+    par_map_broadcast(py, &omega_s_rad_per_s, &omega_i_rad_per_s, |ws, wi| {
+      *(self.0.jsi_singles(ws * RAD * HZ, wi * RAD * HZ) / spdcalc::JSIUnits::new(1.))
+    })
   }
 
   /// Calculate the singles JSI over a range of frequencies
@@ -200,34 +278,47 @@ impl JointSpectrum {
   ///
   /// Returns
   /// -------
-  /// `list` of `float`
-  ///     Vector of singles JSI values
-  pub fn jsi_singles_range(&self, si_range: SIRange) -> Vec<f64> {
-    self
-      .0
-      .jsi_singles_range(si_range)
-      .into_iter()
-      .map(|jsi| *(jsi / spdcalc::JSIUnits::new(1.)))
-      .collect()
+  /// `numpy.ndarray` of `float`
+  ///     Array of singles JSI values, shaped like the range
+  pub fn jsi_singles_range<'py>(
+    &self,
+    py: Python<'py>,
+    si_range: SIRange,
+  ) -> PyResult<Bound<'py, PyArrayDyn<f64>>> {
+    let shape = si_range.shape();
+    let jsi = py.allow_threads(|| {
+      self
+        .0
+        .jsi_singles_range(si_range)
+        .into_iter()
+        .map(|jsi| *(jsi / spdcalc::JSIUnits::new(1.)))
+        .collect()
+    });
+    to_shaped_pyarray(py, jsi, shape)
   }
 
   /// Calculate the normalized singles JSI at specific frequencies
   ///
   /// Parameters
   /// ----------
-  /// omega_s_hz : float
-  ///     Signal frequency in Hz
-  /// omega_i_hz : float
-  ///     Idler frequency in Hz
+  /// omega_s_rad_per_s : float or numpy.ndarray
+  ///     Signal angular frequency in radians per second
+  /// omega_i_rad_per_s : float or numpy.ndarray
+  ///     Idler angular frequency in radians per second
   ///
   /// Returns
   /// -------
-  /// `float`
-  ///     The normalized singles JSI value
-  pub fn jsi_singles_normalized(&self, omega_s_hz: f64, omega_i_hz: f64) -> f64 {
-    self
-      .0
-      .jsi_singles_normalized(omega_s_hz * RAD * HZ, omega_i_hz * RAD * HZ)
+  /// `float` or `numpy.ndarray` of `float`
+  ///     The normalized singles JSI value, or an array of values broadcast from the inputs
+  pub fn jsi_singles_normalized(
+    &self,
+    py: Python<'_>,
+    omega_s_rad_per_s: FloatOrArray<'_>,
+    omega_i_rad_per_s: FloatOrArray<'_>,
+  ) -> PyResult<PyObject> {
+    par_map_broadcast(py, &omega_s_rad_per_s, &omega_i_rad_per_s, |ws, wi| {
+      self.0.jsi_singles_normalized(ws * RAD * HZ, wi * RAD * HZ)
+    })
   }
 
   /// Calculate the normalized singles JSI over a range of frequencies
@@ -239,10 +330,162 @@ impl JointSpectrum {
   ///
   /// Returns
   /// -------
-  /// `list` of `float`
-  ///     Vector of normalized singles JSI values
-  pub fn jsi_singles_normalized_range(&self, si_range: SIRange) -> Vec<f64> {
-    self.0.jsi_singles_normalized_range(si_range)
+  /// `numpy.ndarray` of `float`
+  ///     Array of normalized singles JSI values, shaped like the range
+  pub fn jsi_singles_normalized_range<'py>(
+    &self,
+    py: Python<'py>,
+    si_range: SIRange,
+  ) -> PyResult<Bound<'py, PyArrayDyn<f64>>> {
+    let shape = si_range.shape();
+    let values = py.allow_threads(|| self.0.jsi_singles_normalized_range(si_range));
+    to_shaped_pyarray(py, values, shape)
+  }
+
+  /// Calculate the JSA at specific signal and idler vacuum wavelengths
+  ///
+  /// Parameters
+  /// ----------
+  /// lambda_s_nm : float or numpy.ndarray
+  ///     Signal wavelength in nm
+  /// lambda_i_nm : float or numpy.ndarray
+  ///     Idler wavelength in nm
+  ///
+  /// Returns
+  /// -------
+  /// `complex` or `numpy.ndarray` of `complex`
+  ///     The JSA value, or an array of values broadcast from the inputs
+  pub fn jsa_at_wavelengths(
+    &self,
+    py: Python<'_>,
+    lambda_s_nm: FloatOrArray<'_>,
+    lambda_i_nm: FloatOrArray<'_>,
+  ) -> PyResult<PyObject> {
+    par_map_broadcast(py, &lambda_s_nm, &lambda_i_nm, |ls, li| {
+      self.0.jsa(nm_to_omega(ls), nm_to_omega(li))
+    })
+  }
+
+  /// Calculate the normalized JSA at specific signal and idler vacuum wavelengths
+  ///
+  /// Parameters
+  /// ----------
+  /// lambda_s_nm : float or numpy.ndarray
+  ///     Signal wavelength in nm
+  /// lambda_i_nm : float or numpy.ndarray
+  ///     Idler wavelength in nm
+  ///
+  /// Returns
+  /// -------
+  /// `complex` or `numpy.ndarray` of `complex`
+  ///     The normalized JSA value, or an array of values broadcast from the inputs
+  pub fn jsa_normalized_at_wavelengths(
+    &self,
+    py: Python<'_>,
+    lambda_s_nm: FloatOrArray<'_>,
+    lambda_i_nm: FloatOrArray<'_>,
+  ) -> PyResult<PyObject> {
+    par_map_broadcast(py, &lambda_s_nm, &lambda_i_nm, |ls, li| {
+      self.0.jsa_normalized(nm_to_omega(ls), nm_to_omega(li))
+    })
+  }
+
+  /// Calculate the JSI at specific signal and idler vacuum wavelengths
+  ///
+  /// Parameters
+  /// ----------
+  /// lambda_s_nm : float or numpy.ndarray
+  ///     Signal wavelength in nm
+  /// lambda_i_nm : float or numpy.ndarray
+  ///     Idler wavelength in nm
+  ///
+  /// Returns
+  /// -------
+  /// `float` or `numpy.ndarray` of `float`
+  ///     The JSI value, or an array of values broadcast from the inputs
+  pub fn jsi_at_wavelengths(
+    &self,
+    py: Python<'_>,
+    lambda_s_nm: FloatOrArray<'_>,
+    lambda_i_nm: FloatOrArray<'_>,
+  ) -> PyResult<PyObject> {
+    par_map_broadcast(py, &lambda_s_nm, &lambda_i_nm, |ls, li| {
+      *(self.0.jsi(nm_to_omega(ls), nm_to_omega(li)) / spdcalc::JSIUnits::new(1.))
+    })
+  }
+
+  /// Calculate the normalized JSI at specific signal and idler vacuum wavelengths
+  ///
+  /// Parameters
+  /// ----------
+  /// lambda_s_nm : float or numpy.ndarray
+  ///     Signal wavelength in nm
+  /// lambda_i_nm : float or numpy.ndarray
+  ///     Idler wavelength in nm
+  ///
+  /// Returns
+  /// -------
+  /// `float` or `numpy.ndarray` of `float`
+  ///     The normalized JSI value, or an array of values broadcast from the inputs
+  pub fn jsi_normalized_at_wavelengths(
+    &self,
+    py: Python<'_>,
+    lambda_s_nm: FloatOrArray<'_>,
+    lambda_i_nm: FloatOrArray<'_>,
+  ) -> PyResult<PyObject> {
+    par_map_broadcast(py, &lambda_s_nm, &lambda_i_nm, |ls, li| {
+      self.0.jsi_normalized(nm_to_omega(ls), nm_to_omega(li))
+    })
+  }
+
+  /// Calculate the singles JSI at specific signal and idler vacuum wavelengths
+  ///
+  /// Parameters
+  /// ----------
+  /// lambda_s_nm : float or numpy.ndarray
+  ///     Signal wavelength in nm
+  /// lambda_i_nm : float or numpy.ndarray
+  ///     Idler wavelength in nm
+  ///
+  /// Returns
+  /// -------
+  /// `float` or `numpy.ndarray` of `float`
+  ///     The singles JSI value, or an array of values broadcast from the inputs
+  pub fn jsi_singles_at_wavelengths(
+    &self,
+    py: Python<'_>,
+    lambda_s_nm: FloatOrArray<'_>,
+    lambda_i_nm: FloatOrArray<'_>,
+  ) -> PyResult<PyObject> {
+    par_map_broadcast(py, &lambda_s_nm, &lambda_i_nm, |ls, li| {
+      *(self.0.jsi_singles(nm_to_omega(ls), nm_to_omega(li)) / spdcalc::JSIUnits::new(1.))
+    })
+  }
+
+  /// Calculate the normalized singles JSI at specific signal and idler vacuum wavelengths
+  ///
+  /// Parameters
+  /// ----------
+  /// lambda_s_nm : float or numpy.ndarray
+  ///     Signal wavelength in nm
+  /// lambda_i_nm : float or numpy.ndarray
+  ///     Idler wavelength in nm
+  ///
+  /// Returns
+  /// -------
+  /// `float` or `numpy.ndarray` of `float`
+  ///     The normalized singles JSI value, or an array of values broadcast from the inputs
+  pub fn jsi_singles_normalized_at_wavelengths(
+    &self,
+    py: Python<'_>,
+    lambda_s_nm: FloatOrArray<'_>,
+    lambda_i_nm: FloatOrArray<'_>,
+  ) -> PyResult<PyObject> {
+    par_map_broadcast(py, &lambda_s_nm, &lambda_i_nm, |ls, li| {
+      self
+        .0
+        .jsi_singles_normalized(nm_to_omega(ls), nm_to_omega(li))
+    })
   }
 
   /// Calculate the Schmidt number at specific frequencies
@@ -256,19 +499,487 @@ impl JointSpectrum {
   /// -------
   /// `float`
   ///     The Schmidt number
-  pub fn schmidt_number(&self, si_range: SIRange) -> PyResult<f64> {
+  pub fn schmidt_number(&self, py: Python<'_>, si_range: SIRange) -> PyResult<f64> {
     let fs = ::spdcalc::FrequencySpace::try_from(si_range)?;
     Ok(
-      self
-        .0
-        .schmidt_number(fs)
-        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?,
+      py.allow_threads(|| self.0.schmidt_number(fs))
+        .map_err(PySpdcError)?,
     )
   }
+
+  /// Calculate the Schmidt decomposition of the JSA over a frequency range
+  ///
+  /// The JSA is sampled on the grid and normalized so that its squared
+  /// magnitudes sum to one before being decomposed.
+  ///
+  /// Parameters
+  /// ----------
+  /// si_range : SIRange
+  ///     Range of signal and idler frequencies
+  /// n_modes : int, optional
+  ///     Number of (largest) modes to return. Defaults to 10.
+  ///
+  /// Returns
+  /// -------
+  /// `dict`
+  ///     A dictionary with the keys:
+  ///
+  ///     - ``coefficients``: `numpy.ndarray` of `float`, the Schmidt coefficients
+  ///       (mode probabilities) in decreasing order
+  ///     - ``signal_modes``: `numpy.ndarray` of `complex`, shape ``(n_modes, n_signal)``,
+  ///       the signal modes sampled on the signal axis of the grid
+  ///     - ``idler_modes``: `numpy.ndarray` of `complex`, shape ``(n_modes, n_idler)``,
+  ///       the idler modes sampled on the idler axis of the grid
+  ///     - ``purity``: `float`, the heralded single photon purity
+  ///     - ``entropy``: `float`, the entanglement entropy in bits
+  ///     - ``schmidt_number``: `float`, the inverse of the purity
+  ///
+  ///     The purity, entropy and Schmidt number use all modes, not only those returned.
+  #[pyo3(signature = (si_range, n_modes=10))]
+  pub fn schmidt_decomposition<'py>(
+    &self,
+    py: Python<'py>,
+    si_range: SIRange,
+    n_modes: usize,
+  ) -> PyResult<Bound<'py, PyDict>> {
+    let shape = si_range.shape();
+    let fs = ::spdcalc::FrequencySpace::try_from(si_range)?;
+    let (n_idler, n_signal) = (shape[0], shape[1]);
+    let decomposition = py.allow_threads(|| {
+      SchmidtDecomposition::new(&self.0.jsa_range(fs), n_signal, n_idler, n_modes)
+    })?;
+
+    let n_modes = decomposition.n_modes;
+    let dict = PyDict::new_bound(py);
+    dict.set_item(
+      "coefficients",
+      to_shaped_pyarray(py, decomposition.coefficients, vec![n_modes])?,
+    )?;
+    dict.set_item(
+      "signal_modes",
+      to_shaped_pyarray(py, decomposition.signal_modes, vec![n_modes, n_signal])?,
+    )?;
+    dict.set_item(
+      "idler_modes",
+      to_shaped_pyarray(py, decomposition.idler_modes, vec![n_modes, n_idler])?,
+    )?;
+    dict.set_item("purity", decomposition.purity)?;
+    dict.set_item("entropy", decomposition.entropy)?;
+    dict.set_item("schmidt_number", 1. / decomposition.purity)?;
+    Ok(dict)
+  }
+
+  /// Calculate the joint temporal amplitude (JTA) over a frequency range
+  ///
+  /// The JTA is the 2D Fourier transform of the JSA. The JSA is zero-padded
+  /// before transforming, and the result is normalized so its squared
+  /// magnitudes sum to one.
+  ///
+  /// Parameters
+  /// ----------
+  /// si_range : SIRange
+  ///     Range of signal and idler frequencies
+  /// padding : int, optional
+  ///     Factor to zero-pad the JSA by along each axis. Defaults to 1 (no padding).
+  ///
+  /// Returns
+  /// -------
+  /// `dict`
+  ///     A dictionary with the keys:
+  ///
+  ///     - ``jta``: `numpy.ndarray` of `complex`, shape ``(n_t_idler, n_t_signal)``
+  ///     - ``t_signal_s``: `numpy.ndarray` of `float`, the signal time axis in seconds
+  ///     - ``t_idler_s``: `numpy.ndarray` of `float`, the idler time axis in seconds
+  #[pyo3(signature = (si_range, padding=1))]
+  pub fn jta_range<'py>(
+    &self,
+    py: Python<'py>,
+    si_range: SIRange,
+    padding: usize,
+  ) -> PyResult<Bound<'py, PyDict>> {
+    let jta = self.joint_temporal_amplitude(py, si_range, padding)?;
+    let shape = vec![jta.t_idler.len(), jta.t_signal.len()];
+    let dict = PyDict::new_bound(py);
+    dict.set_item("jta", to_shaped_pyarray(py, jta.values, shape)?)?;
+    dict.set_item("t_signal_s", PyArray1::from_vec_bound(py, jta.t_signal))?;
+    dict.set_item("t_idler_s", PyArray1::from_vec_bound(py, jta.t_idler))?;
+    Ok(dict)
+  }
+
+  /// Calculate the joint temporal intensity (JTI) over a frequency range
+  ///
+  /// This is the squared magnitude of the JTA (see :meth:`jta_range`).
+  ///
+  /// Parameters
+  /// ----------
+  /// si_range : SIRange
+  ///     Range of signal and idler frequencies
+  /// padding : int, optional
+  ///     Factor to zero-pad the JSA by along each axis. Defaults to 1 (no padding).
+  ///
+  /// Returns
+  /// -------
+  /// `dict`
+  ///     A dictionary with the keys:
+  ///
+  ///     - ``jti``: `numpy.ndarray` of `float`, shape ``(n_t_idler, n_t_signal)``
+  ///     - ``t_signal_s``: `numpy.ndarray` of `float`, the signal time axis in seconds
+  ///     - ``t_idler_s``: `numpy.ndarray` of `float`, the idler time axis in seconds
+  #[pyo3(signature = (si_range, padding=1))]
+  pub fn jti_range<'py>(
+    &self,
+    py: Python<'py>,
+    si_range: SIRange,
+    padding: usize,
+  ) -> PyResult<Bound<'py, PyDict>> {
+    let jta = self.joint_temporal_amplitude(py, si_range, padding)?;
+    let shape = vec![jta.t_idler.len(), jta.t_signal.len()];
+    let dict = PyDict::new_bound(py);
+    dict.set_item("jti", to_shaped_pyarray(py, jta.intensities(), shape)?)?;
+    dict.set_item("t_signal_s", PyArray1::from_vec_bound(py, jta.t_signal))?;
+    dict.set_item("t_idler_s", PyArray1::from_vec_bound(py, jta.t_idler))?;
+    Ok(dict)
+  }
+
+  /// Calculate the coincidence window as the FWHM of the distribution of
+  /// signal-idler arrival time differences, from the JTI
+  ///
+  /// Parameters
+  /// ----------
+  /// si_range : SIRange
+  ///     Range of signal and idler frequencies
+  /// padding : int, optional
+  ///     Factor to zero-pad the JSA by along each axis. Defaults to 1 (no padding).
+  ///
+  /// Returns
+  /// -------
+  /// `float`
+  ///     The coincidence window FWHM in seconds
+  #[pyo3(signature = (si_range, padding=1))]
+  pub fn coincidence_window_fwhm(
+    &self,
+    py: Python<'_>,
+    si_range: SIRange,
+    padding: usize,
+  ) -> PyResult<f64> {
+    let jta = self.joint_temporal_amplitude(py, si_range, padding)?;
+    Ok(py.allow_threads(|| jta.coincidence_window_fwhm())?)
+  }
+
+  /// Calculate the signal marginal spectrum over a frequency or wavelength range
+  ///
+  /// This is the JSI integrated over the idler axis of the range.
+  ///
+  /// Parameters
+  /// ----------
+  /// si_range : FrequencySpace or WavelengthSpace
+  ///     Range of signal and idler frequencies or wavelengths
+  /// singles : bool, optional
+  ///     Use the singles JSI rather than the coincidences JSI. Defaults to False.
+  ///
+  /// Returns
+  /// -------
+  /// `dict`
+  ///     A dictionary with the keys:
+  ///
+  ///     - ``axis``: `numpy.ndarray` of `float`, the signal axis of the range
+  ///       (rad/s for frequency spaces, meters for wavelength spaces)
+  ///     - ``values``: `numpy.ndarray` of `float`, the marginal spectrum on that axis
+  ///     - ``fwhm``: `float` or `None`, the FWHM of the marginal in axis units,
+  ///       or `None` if it does not fall below half maximum within the range
+  ///     - ``centroid``: `float`, the centroid of the marginal in axis units
+  #[pyo3(signature = (si_range, singles=false))]
+  pub fn signal_marginal<'py>(
+    &self,
+    py: Python<'py>,
+    si_range: SIRange,
+    singles: bool,
+  ) -> PyResult<Bound<'py, PyDict>> {
+    self.marginal(py, si_range, singles, false)
+  }
+
+  /// Calculate the idler marginal spectrum over a frequency or wavelength range
+  ///
+  /// This is the JSI integrated over the signal axis of the range.
+  ///
+  /// Parameters
+  /// ----------
+  /// si_range : FrequencySpace or WavelengthSpace
+  ///     Range of signal and idler frequencies or wavelengths
+  /// singles : bool, optional
+  ///     Use the singles JSI rather than the coincidences JSI. Defaults to False.
+  ///
+  /// Returns
+  /// -------
+  /// `dict`
+  ///     A dictionary with the keys:
+  ///
+  ///     - ``axis``: `numpy.ndarray` of `float`, the idler axis of the range
+  ///       (rad/s for frequency spaces, meters for wavelength spaces)
+  ///     - ``values``: `numpy.ndarray` of `float`, the marginal spectrum on that axis
+  ///     - ``fwhm``: `float` or `None`, the FWHM of the marginal in axis units,
+  ///       or `None` if it does not fall below half maximum within the range
+  ///     - ``centroid``: `float`, the centroid of the marginal in axis units
+  #[pyo3(signature = (si_range, singles=false))]
+  pub fn idler_marginal<'py>(
+    &self,
+    py: Python<'py>,
+    si_range: SIRange,
+    singles: bool,
+  ) -> PyResult<Bound<'py, PyDict>> {
+    self.marginal(py, si_range, singles, true)
+  }
+
+  /// Calculate the heralded single photon purity over a frequency range
+  ///
+  /// This is the sum of the squared Schmidt coefficients of the JSA
+  /// (see :meth:`schmidt_decomposition`).
+  ///
+  /// Parameters
+  /// ----------
+  /// si_range : SIRange
+  ///     Range of signal and idler frequencies
+  ///
+  /// Returns
+  /// -------
+  /// `float`
+  ///     The heralded purity
+  pub fn heralded_purity(&self, py: Python<'_>, si_range: SIRange) -> PyResult<f64> {
+    let shape = si_range.shape();
+    let fs = ::spdcalc::FrequencySpace::try_from(si_range)?;
+    let decomposition =
+      py.allow_threads(|| SchmidtDecomposition::new(&self.0.jsa_range(fs), shape[1], shape[0], 0))?;
+    Ok(decomposition.purity)
+  }
+
+  /// Save a quantity computed over a range to an ``.npz`` file, with its metadata
+  ///
+  /// The file holds everything needed to restore the computation with
+  /// :meth:`load_grid`, as the arrays:
+  ///
+  /// - ``values``: the quantity, shaped like the range
+  /// - ``x_values``, ``y_values``: the axes of the range, from its ``x_values()`` and
+  ///   ``y_values()``
+  /// - ``axis_kind``: the kind of range, one of ``"frequency"``, ``"wavelength"``,
+  ///   ``"sum_diff"``, ``"frequency_array"`` or ``"wavelength_array"``
+  /// - ``quantity``: the name of the quantity
+  /// - ``config``: the full precision config of the setup, as JSON
+  /// - ``auto_fields``: the fields of the config that were ``auto``
+  ///   (see :attr:`SPDC.auto_fields`), as JSON
+  /// - ``config_yaml``: a readable copy of the config, from
+  ///   ``SPDC.to_yaml(preserve_auto=True)``
+  /// - ``integrator``: the integration method, as JSON
+  ///
+  /// Parameters
+  /// ----------
+  /// path : str or os.PathLike
+  ///     The file to write. numpy appends ``.npz`` to names without that extension.
+  /// si_range : SIRange
+  ///     Range of signal and idler frequencies or wavelengths
+  /// quantity : str, optional
+  ///     One of ``"jsa"``, ``"jsa_normalized"``, ``"jsi"``, ``"jsi_normalized"``,
+  ///     ``"jsi_singles"`` or ``"jsi_singles_normalized"``, computed with the
+  ///     corresponding ``*_range`` method. Defaults to ``"jsi"``.
+  #[pyo3(signature = (path, si_range, quantity="jsi"))]
+  pub fn save_grid(
+    &self,
+    py: Python<'_>,
+    path: &Bound<'_, PyAny>,
+    si_range: SIRange,
+    quantity: &str,
+  ) -> PyResult<()> {
+    let (x, y) = si_range.axes();
+    let kind = si_range.kind();
+    let values = match quantity {
+      "jsa" => self.jsa_range(py, si_range)?.into_any(),
+      "jsa_normalized" => self.jsa_normalized_range(py, si_range)?.into_any(),
+      "jsi" => self.jsi_range(py, si_range)?.into_any(),
+      "jsi_normalized" => self.jsi_normalized_range(py, si_range)?.into_any(),
+      "jsi_singles" => self.jsi_singles_range(py, si_range)?.into_any(),
+      "jsi_singles_normalized" => self.jsi_singles_normalized_range(py, si_range)?.into_any(),
+      other => {
+        return Err(PyValueError::new_err(format!(
+          "Unknown quantity '{}'. Expected one of: {}",
+          other,
+          GRID_QUANTITIES.join(", ")
+        )))
+      }
+    };
+    let arrays = PyDict::new_bound(py);
+    arrays.set_item("values", values)?;
+    arrays.set_item("x_values", PyArray1::from_vec_bound(py, x))?;
+    arrays.set_item("y_values", PyArray1::from_vec_bound(py, y))?;
+    arrays.set_item("axis_kind", kind)?;
+    arrays.set_item("quantity", quantity)?;
+    let (config, auto_fields) = self.1.state()?;
+    arrays.set_item("config", config)?;
+    arrays.set_item(
+      "auto_fields",
+      serde_json::to_string(&auto_fields).map_err(PySpdcError::from)?,
+    )?;
+    arrays.set_item("config_yaml", self.1.to_yaml(true)?)?;
+    arrays.set_item(
+      "integrator",
+      serde_json::to_string(&self.2 .0).map_err(PySpdcError::from)?,
+    )?;
+    py.import_bound("numpy")?
+      .call_method("savez_compressed", (path,), Some(&arrays))?;
+    Ok(())
+  }
+
+  /// Load a grid saved with :meth:`save_grid`
+  ///
+  /// Parameters
+  /// ----------
+  /// path : str or os.PathLike
+  ///     The ``.npz`` file to read
+  ///
+  /// Returns
+  /// -------
+  /// `dict`
+  ///     A dictionary with the keys:
+  ///
+  ///     - ``values``: `numpy.ndarray`, the saved quantity, shaped like the range
+  ///     - ``x_values``, ``y_values``: `numpy.ndarray` of `float`, the axes of the range
+  ///     - ``axis_kind``: `str`, the kind of range
+  ///     - ``quantity``: `str`, the name of the quantity
+  ///     - ``spdc``: :class:`SPDC`, the setup, reconstructed from the saved config
+  ///     - ``si_range``: the range, reconstructed as a :class:`FrequencySpace`,
+  ///       :class:`WavelengthSpace`, :class:`SumDiffFrequencySpace`,
+  ///       :class:`FrequencyArray` or :class:`WavelengthArray`
+  ///     - ``integrator``: :class:`Integrator`, the integration method
+  ///
+  ///     The setup and integrator recreate the spectrum with ``spdc.joint_spectrum(integrator)``.
+  #[staticmethod]
+  pub fn load_grid<'py>(py: Python<'py>, path: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyDict>> {
+    let npz = py.import_bound("numpy")?.call_method1("load", (path,))?;
+    let grid = Self::read_grid(py, &npz);
+    // close the file even if it is not a valid grid
+    npz.call_method0("close")?;
+    grid
+  }
+}
+
+/// Quantities that :meth:`JointSpectrum.save_grid` computes
+const GRID_QUANTITIES: [&str; 6] = [
+  "jsa",
+  "jsa_normalized",
+  "jsi",
+  "jsi_normalized",
+  "jsi_singles",
+  "jsi_singles_normalized",
+];
+
+/// Convert a vacuum wavelength in nm to an angular frequency
+fn nm_to_omega(lambda_nm: f64) -> Frequency {
+  vacuum_wavelength_to_frequency(lambda_nm * NANO * M)
 }
 
-impl From<::spdcalc::JointSpectrum> for JointSpectrum {
-  fn from(js: ::spdcalc::JointSpectrum) -> Self {
-    Self(js)
+impl JointSpectrum {
+  /// Read the contents of an open ``.npz`` file written by `save_grid`
+  fn read_grid<'py>(py: Python<'py>, npz: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyDict>> {
+    let text =
+      |name: &str| -> PyResult<String> { npz.get_item(name)?.call_method0("item")?.extract() };
+    let axis =
+      |name: &str| -> PyResult<Vec<f64>> { npz.get_item(name)?.call_method0("tolist")?.extract() };
+
+    let kind = text("axis_kind")?;
+    let si_range = SIRange::from_axes(&kind, &axis("x_values")?, &axis("y_values")?)?;
+    let spdc = if npz.contains("auto_fields")? {
+      let auto_fields: Vec<String> =
+        serde_json::from_str(&text("auto_fields")?).map_err(PyConfigError::from)?;
+      SPDC::from_state(&text("config")?, &auto_fields)?
+    } else {
+      // grids saved before the full precision config was stored
+      SPDC::from_yaml(&text("config")?)?
+    };
+    let integrator =
+      Integrator(serde_json::from_str(&text("integrator")?).map_err(PyConfigError::from)?);
+
+    let dict = PyDict::new_bound(py);
+    dict.set_item("values", npz.get_item("values")?)?;
+    dict.set_item("x_values", npz.get_item("x_values")?)?;
+    dict.set_item("y_values", npz.get_item("y_values")?)?;
+    dict.set_item("axis_kind", kind)?;
+    dict.set_item("quantity", text("quantity")?)?;
+    dict.set_item("spdc", spdc.into_py(py))?;
+    dict.set_item("si_range", si_range.into_py(py))?;
+    dict.set_item("integrator", integrator.into_py(py))?;
+    Ok(dict)
+  }
+
+  /// Fourier transform the JSA over a frequency range into the time domain
+  fn joint_temporal_amplitude(
+    &self,
+    py: Python<'_>,
+    si_range: SIRange,
+    padding: usize,
+  ) -> PyResult<JointTemporalAmplitude> {
+    let fs = ::spdcalc::FrequencySpace::try_from(si_range)?;
+    let step = |(min, max, n): (Frequency, Frequency, usize)| {
+      (
+        n,
+        (*((max - min) / (RAD * HZ))).abs() / (n.max(2) - 1) as f64,
+      )
+    };
+    let (signal, idler) = (step(fs.steps().0), step(fs.steps().1));
+    py.allow_threads(|| {
+      JointTemporalAmplitude::from_jsa(&self.0.jsa_range(fs), signal, idler, padding)
+    })
+    .map_err(PyErr::from)
+  }
+
+  /// Integrate the JSI over one axis of a frequency or wavelength space
+  fn marginal<'py>(
+    &self,
+    py: Python<'py>,
+    si_range: SIRange,
+    singles: bool,
+    idler: bool,
+  ) -> PyResult<Bound<'py, PyDict>> {
+    let (x, y) = si_range.signal_idler_axes()?;
+    let n_signal = x.len();
+    let (axis, other) = if idler { (y, x) } else { (x, y) };
+    let step = if other.len() > 1 {
+      (other[1] - other[0]).abs()
+    } else {
+      1.
+    };
+    let jsi: Vec<f64> = py.allow_threads(|| {
+      if singles {
+        self.0.jsi_singles_range(si_range)
+      } else {
+        self.0.jsi_range(si_range)
+      }
+      .into_iter()
+      .map(|jsi| *(jsi / spdcalc::JSIUnits::new(1.)))
+      .collect()
+    });
+    // rows are idler values, columns are signal values
+    let values: Vec<f64> = (0..axis.len())
+      .map(|k| {
+        let sum: f64 = if idler {
+          jsi[k * n_signal..(k + 1) * n_signal].iter().sum()
+        } else {
+          jsi.iter().skip(k).step_by(n_signal).sum()
+        };
+        sum * step
+      })
+      .collect();
+
+    let dict = PyDict::new_bound(py);
+    dict.set_item("fwhm", fwhm(&axis, &values))?;
+    dict.set_item("centroid", centroid(&axis, &values))?;
+    dict.set_item("axis", PyArray1::from_vec_bound(py, axis))?;
+    dict.set_item("values", PyArray1::from_vec_bound(py, values))?;
+    Ok(dict)
+  }
+
+  pub(crate) fn from_setup(spdc: SPDC, integrator: Integrator) -> Self {
+    Self(
+      crate::pump::Spectrum::new(&spdc, integrator.0),
+      spdc,
+      integrator,
+    )
   }
 }
@@ -1,6 +1,10 @@
 use pyo3::prelude::*;
+mod config;
+mod diff;
 mod error;
 use error::*;
+mod arrays;
+use arrays::*;
 mod spdc;
 use spdc::SPDC;
 mod integrator;
@@ -9,13 +13,37 @@ mod spaces;
 use spaces::*;
 mod joint_spectrum;
 use joint_spectrum::*;
+mod schmidt;
+use schmidt::*;
+mod math;
+mod optimize;
+mod pump;
+mod schema;
+use pump::PumpSpectrum;
+mod sweep;
+mod temporal;
+mod validate;
 use spdcalc::{
   dim::{
     f64prefixes::NANO,
     ucum::{HZ, K, M, RAD},
   },
-  Complex, CrystalMeta, CrystalType, JsiNorm, JsiSinglesNorm, PerMeter3, PerMeter4,
+  CrystalMeta, CrystalType, JsiNorm, JsiSinglesNorm, PerMeter3, PerMeter4,
 };
+use std::hash::{Hash, Hasher};
+use temporal::*;
+
+/// Hash a value for use in python `__hash__` implementations
+pub(crate) fn hash_of<T: Hash + ?Sized>(value: &T) -> u64 {
+  let mut hasher = std::collections::hash_map::DefaultHasher::new();
+  value.hash(&mut hasher);
+  hasher.finish()
+}
+
+/// Hashable key for a float, such that `0.0` and `-0.0` hash the same
+pub(crate) fn float_key(value: f64) -> u64 {
+  (value + 0.0).to_bits()
+}
 
 /// Get the normalized joint spectral intensity of the SPDC setup within a given range
 ///
@@ -28,15 +56,21 @@ use spdcalc::{
 ///
 /// Returns
 /// -------
-/// `list`
-///     The joint spectral intensities
+/// `numpy.ndarray`
+///     The joint spectral intensities, shaped like the range
 #[pyfunction]
 #[pyo3(signature = (spdc, si_range, integrator=None))]
-fn get_jsi(spdc: &SPDC, si_range: SIRange, integrator: Option<Integrator>) -> Vec<f64> {
-  spdc
-    .0
-    .joint_spectrum(integrator.unwrap_or_default().0)
-    .jsi_normalized_range(si_range)
+fn get_jsi<'py>(
+  py: Python<'py>,
+  spdc: &SPDC,
+  si_range: SIRange,
+  integrator: Option<Integrator>,
+) -> PyResult<Bound<'py, numpy::PyArrayDyn<f64>>> {
+  let shape = si_range.shape();
+  let integrator = integrator.unwrap_or_default().0;
+  let jsi =
+    py.allow_threads(|| pump::Spectrum::new(spdc, integrator).jsi_normalized_range(si_range));
+  to_shaped_pyarray(py, jsi, shape)
 }
 
 /// Get all crystal metadata
@@ -89,9 +123,9 @@ fn get_crystal_indices(
 ///
 /// Parameters
 /// ----------
-/// `omega_s_rad_per_s`: float
+/// `omega_s_rad_per_s`: float or numpy.ndarray
 ///     The signal frequency in radians per second
-/// `omega_i_rad_per_s`: float
+/// `omega_i_rad_per_s`: float or numpy.ndarray
 ///     The idler frequency in radians per second
 /// `spdc`: SPDC
 ///     The SPDC object
@@ -100,22 +134,22 @@ fn get_crystal_indices(
 ///
 /// Returns
 /// -------
-/// `complex`
-///    The phasematching function amplitude
+/// `complex` or `numpy.ndarray` of `complex`
+///    The phasematching function amplitude, or an array of values broadcast from the inputs
 #[pyfunction]
 #[pyo3(signature = (omega_s_rad_per_s, omega_i_rad_per_s, spdc, integrator=None))]
 fn phasematch_fiber_coupling(
-  omega_s_rad_per_s: f64,
-  omega_i_rad_per_s: f64,
+  py: Python<'_>,
+  omega_s_rad_per_s: FloatOrArray<'_>,
+  omega_i_rad_per_s: FloatOrArray<'_>,
   spdc: &SPDC,
   integrator: Option<Integrator>,
-) -> Complex<f64> {
-  *(::spdcalc::phasematch_fiber_coupling(
-    omega_s_rad_per_s * RAD * HZ,
-    omega_i_rad_per_s * RAD * HZ,
-    &spdc.0,
-    integrator.unwrap_or_default().0,
-  ) / PerMeter4::new(1.0))
+) -> PyResult<PyObject> {
+  let integrator = integrator.unwrap_or_default().0;
+  par_map_broadcast(py, &omega_s_rad_per_s, &omega_i_rad_per_s, |ws, wi| {
+    *(::spdcalc::phasematch_fiber_coupling(ws * RAD * HZ, wi * RAD * HZ, &spdc.0, integrator)
+      / PerMeter4::new(1.0))
+  })
 }
 
 /// Get the singles phasematching function *intensity* for a given setup at a given frequencies
@@ -167,10 +201,10 @@ fn phasematch_singles_fiber_coupling(
 /// `float`
 ///     The normalization factor
 #[pyfunction]
-fn jsi_normalization(omega_i_rad_per_s: f64, omega_s_rad_per_s: f64, spdc: &SPDC) -> f64 {
+fn jsi_normalization(omega_s_rad_per_s: f64, omega_i_rad_per_s: f64, spdc: &SPDC) -> f64 {
   *(::spdcalc::jsi_normalization(
-    omega_i_rad_per_s * RAD * HZ,
     omega_s_rad_per_s * RAD * HZ,
+    omega_i_rad_per_s * RAD * HZ,
     &spdc.0,
   ) / JsiNorm::new(1.))
 }
@@ -191,35 +225,45 @@ fn jsi_normalization(omega_i_rad_per_s: f64, omega_s_rad_per_s: f64, spdc: &SPDC
 /// `float`
 ///     The normalization factor
 #[pyfunction]
-fn jsi_singles_normalization(omega_i_rad_per_s: f64, omega_s_rad_per_s: f64, spdc: &SPDC) -> f64 {
+fn jsi_singles_normalization(omega_s_rad_per_s: f64, omega_i_rad_per_s: f64, spdc: &SPDC) -> f64 {
   *(::spdcalc::jsi_singles_normalization(
-    omega_i_rad_per_s * RAD * HZ,
     omega_s_rad_per_s * RAD * HZ,
+    omega_i_rad_per_s * RAD * HZ,
     &spdc.0,
   ) / JsiSinglesNorm::new(1.))
 }
 
-/// Get the pump spectral amplitude for a given setup at a given frequency
+/// Get the pump spectral amplitude for a given setup
+///
+/// Uses the pump spectrum of the setup (see :attr:`SPDC.pump_spectrum`).
 ///
 /// Parameters
 /// ----------
-/// `omega_rad_per_s`: float
+/// `omega_rad_per_s`: float or numpy.ndarray
 ///     The frequency in radians per second
 /// `spdc`: SPDC
 ///     The SPDC object
 ///
 /// Returns
 /// -------
-/// `float`
-///     The pump spectral amplitude
+/// `float` or `numpy.ndarray`
+///     The pump spectral amplitude, shaped like the input
 #[pyfunction]
-fn pump_spectral_amplitude(omega_rad_per_s: f64, spdc: SPDC) -> f64 {
-  ::spdcalc::pump_spectral_amplitude(omega_rad_per_s * RAD * HZ, &spdc.0)
+fn pump_spectral_amplitude(
+  py: Python<'_>,
+  omega_rad_per_s: FloatOrArray<'_>,
+  spdc: &SPDC,
+) -> PyResult<PyObject> {
+  par_map(py, &omega_rad_per_s, |omega| {
+    spdc.1.amplitude(omega * RAD * HZ, &spdc.0)
+  })
 }
 
 /// Module containing the python bindings for SPDCalc
 #[pymodule(name = "spdcalc")]
 fn spdcalc_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+  error::add_exceptions(m)?;
+
   m.add_class::<SPDC>()?;
   m.add_class::<Integrator>()?;
   m.add_class::<JointSpectrum>()?;
@@ -238,6 +282,9 @@ fn spdcalc_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
   m.add_function(wrap_pyfunction!(jsi_normalization, m)?)?;
   m.add_function(wrap_pyfunction!(jsi_singles_normalization, m)?)?;
   m.add_function(wrap_pyfunction!(pump_spectral_amplitude, m)?)?;
+  m.add_function(wrap_pyfunction!(sweep::sweep, m)?)?;
+  m.add_function(wrap_pyfunction!(optimize::optimize, m)?)?;
+  m.add_function(wrap_pyfunction!(schema::config_schema, m)?)?;
 
   Ok(())
 }
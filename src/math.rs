@@ -0,0 +1,53 @@
+/// Full width at half maximum of a sampled peak
+///
+/// The half maximum crossings are linearly interpolated between samples.
+/// Returns `None` if the peak does not fall below half maximum on both sides
+/// within the samples.
+pub(crate) fn fwhm(x: &[f64], y: &[f64]) -> Option<f64> {
+  let (peak, max) = y
+    .iter()
+    .copied()
+    .enumerate()
+    .max_by(|a, b| a.1.total_cmp(&b.1))?;
+  let half = max / 2.;
+  let crossing = |i: usize, j: usize| x[i] + (half - y[i]) * (x[j] - x[i]) / (y[j] - y[i]);
+  let left = (0..peak).rev().find(|&i| y[i] < half)?;
+  let right = (peak + 1..y.len()).find(|&i| y[i] < half)?;
+  Some(crossing(right - 1, right) - crossing(left + 1, left))
+}
+
+/// Centroid (weighted mean position) of a sampled distribution
+pub(crate) fn centroid(x: &[f64], y: &[f64]) -> f64 {
+  let total: f64 = y.iter().sum();
+  x.iter().zip(y).map(|(x, y)| x * y).sum::<f64>() / total
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn fwhm_of_gaussian() {
+    let sigma = 1.3;
+    let x: Vec<f64> = (0..2001).map(|i| -10. + 0.01 * i as f64).collect();
+    let y: Vec<f64> = x
+      .iter()
+      .map(|x| (-x * x / (2. * sigma * sigma)).exp())
+      .collect();
+    let expected = 2. * (2. * 2f64.ln()).sqrt() * sigma;
+    assert!((fwhm(&x, &y).unwrap() - expected).abs() < 1e-4);
+  }
+
+  #[test]
+  fn centroid_of_asymmetric_distribution() {
+    assert_eq!(centroid(&[0., 1., 2.], &[1., 2., 1.]), 1.);
+    assert_eq!(centroid(&[0., 1., 2.], &[0., 1., 3.]), 1.75);
+  }
+
+  #[test]
+  fn fwhm_of_truncated_peak() {
+    let x = [0., 1., 2.];
+    let y = [0.9, 1., 0.2];
+    assert_eq!(fwhm(&x, &y), None);
+  }
+}
@@ -0,0 +1,271 @@
+use crate::sweep::{evaluate_metric, Field, Metric};
+use crate::*;
+use numpy::PyArray1;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::PyDict;
+use spdcalc::FrequencySpace;
+
+/// Minimize a function over the unit box with the Nelder-Mead simplex method
+///
+/// Points outside the box are projected onto it before evaluating. Stops after
+/// `max_iterations`, or once the function values and the simplex both span
+/// less than `tolerance` (relative to the best value, and to the box, respectively).
+pub(crate) fn nelder_mead<E, F>(
+  mut f: F,
+  x0: Vec<f64>,
+  max_iterations: usize,
+  tolerance: f64,
+) -> Result<(Vec<f64>, f64), E>
+where
+  F: FnMut(&[f64]) -> Result<f64, E>,
+{
+  let clamp = |x: Vec<f64>| -> Vec<f64> { x.into_iter().map(|v| v.clamp(0., 1.)).collect() };
+  // NaN is never an improvement
+  let mut eval = |x: &[f64]| f(x).map(|v| if v.is_nan() { f64::INFINITY } else { v });
+  let n = x0.len();
+  let x0 = clamp(x0);
+
+  // initial simplex steps into the interior of the box
+  let mut simplex = vec![x0.clone()];
+  for i in 0..n {
+    let mut x = x0.clone();
+    x[i] += if x[i] > 0.5 { -0.1 } else { 0.1 };
+    simplex.push(x);
+  }
+  let mut values = simplex
+    .iter()
+    .map(|x| eval(x))
+    .collect::<Result<Vec<f64>, E>>()?;
+
+  for _ in 0..max_iterations {
+    // sort vertices from best to worst
+    let mut order: Vec<usize> = (0..=n).collect();
+    order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+    simplex = order.iter().map(|&i| simplex[i].clone()).collect();
+    values = order.iter().map(|&i| values[i]).collect();
+
+    let (best, worst) = (values[0], values[n]);
+    let spread = (worst - best).abs();
+    let size = simplex[1..]
+      .iter()
+      .flat_map(|x| x.iter().zip(&simplex[0]).map(|(a, b)| (a - b).abs()))
+      .fold(0., f64::max);
+    if spread <= tolerance * best.abs() && size <= tolerance {
+      break;
+    }
+
+    let centroid: Vec<f64> = (0..n)
+      .map(|j| simplex[..n].iter().map(|x| x[j]).sum::<f64>() / n as f64)
+      .collect();
+    let towards = |t: f64| -> Vec<f64> {
+      clamp(
+        centroid
+          .iter()
+          .zip(&simplex[n])
+          .map(|(c, w)| c + t * (w - c))
+          .collect(),
+      )
+    };
+
+    let reflected = towards(-1.);
+    let f_reflected = eval(&reflected)?;
+    if f_reflected < values[0] {
+      let expanded = towards(-2.);
+      let f_expanded = eval(&expanded)?;
+      if f_expanded < f_reflected {
+        simplex[n] = expanded;
+        values[n] = f_expanded;
+      } else {
+        simplex[n] = reflected;
+        values[n] = f_reflected;
+      }
+    } else if f_reflected < values[n - 1] {
+      simplex[n] = reflected;
+      values[n] = f_reflected;
+    } else {
+      let contracted = if f_reflected < values[n] {
+        towards(-0.5)
+      } else {
+        towards(0.5)
+      };
+      let f_contracted = eval(&contracted)?;
+      if f_contracted < values[n].min(f_reflected) {
+        simplex[n] = contracted;
+        values[n] = f_contracted;
+      } else {
+        // shrink towards the best vertex
+        for i in 1..=n {
+          simplex[i] = simplex[0]
+            .iter()
+            .zip(&simplex[i])
+            .map(|(b, x)| b + 0.5 * (x - b))
+            .collect();
+          values[i] = eval(&simplex[i])?;
+        }
+      }
+    }
+  }
+
+  let best = (0..=n)
+    .min_by(|&a, &b| values[a].total_cmp(&values[b]))
+    .unwrap();
+  Ok((simplex[best].clone(), values[best]))
+}
+
+/// Optimize a metric over a subset of SPDC fields within bounds
+///
+/// Uses the Nelder-Mead simplex method, starting from the values of the given
+/// setup (clamped to the bounds). The metric is maximized, so to minimize a
+/// quantity choose a metric that inverts it (e.g. ``"inverse_schmidt_number"``
+/// or ``"heralded_purity"`` rather than ``"schmidt_number"``).
+///
+/// Parameters
+/// ----------
+/// spdc : SPDC
+///     The setup to start from
+/// bounds : dict
+///     Mapping of SPDC property names (e.g. ``"signal_waist_um"``) to
+///     ``(min, max)`` bounds. Only these fields are varied.
+/// metric : str, optional
+///     The quantity to maximize. Any metric accepted by :func:`sweep`.
+///     Defaults to ``"symmetric_efficiency"``.
+/// si_range : SIRange, optional
+///     Range of signal and idler frequencies. Defaults to the optimum range
+///     (with 100 steps) of each evaluated setup.
+/// integrator : Integrator, optional
+///     The integrator to use, which defaults to a simple Simpson's rule
+/// max_iterations : int, optional
+///     Maximum number of simplex iterations. Defaults to 100.
+/// tolerance : float, optional
+///     Relative tolerance for convergence. Defaults to 1e-6.
+///
+/// Returns
+/// -------
+/// `tuple` of (:class:`SPDC`, `dict`)
+///     The optimized setup, and the trace of every evaluation as a dictionary
+///     with the keys ``fields`` (the names of the varied fields), ``params``
+///     (`numpy.ndarray` of shape ``(n_evaluations, n_fields)``) and ``values``
+///     (`numpy.ndarray` of the metric at each evaluation).
+#[pyfunction]
+#[pyo3(signature = (
+  spdc,
+  bounds,
+  metric = Metric::SymmetricEfficiency,
+  si_range = None,
+  integrator = None,
+  max_iterations = 100,
+  tolerance = 1e-6
+))]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn optimize<'py>(
+  py: Python<'py>,
+  spdc: &SPDC,
+  bounds: &Bound<'py, PyDict>,
+  metric: Metric,
+  si_range: Option<SIRange>,
+  integrator: Option<Integrator>,
+  max_iterations: usize,
+  tolerance: f64,
+) -> PyResult<(SPDC, Bound<'py, PyDict>)> {
+  let fields = bounds
+    .iter()
+    .map(|(name, bounds)| {
+      let name = name.extract::<String>()?;
+      let field = Field::by_name(&name)?;
+      let (min, max) = bounds.extract::<(f64, f64)>()?;
+      if min.partial_cmp(&max) != Some(std::cmp::Ordering::Less) {
+        return Err(PyValueError::new_err(format!(
+          "Bounds for '{}' must satisfy min < max, got ({}, {})",
+          name, min, max
+        )));
+      }
+      Ok((field, min, max))
+    })
+    .collect::<PyResult<Vec<_>>>()?;
+  let range = si_range.map(FrequencySpace::try_from).transpose()?;
+  let integrator = integrator.unwrap_or_default().0;
+
+  let to_params = |u: &[f64]| -> Vec<f64> {
+    u.iter()
+      .zip(&fields)
+      .map(|(u, (_, min, max))| min + u * (max - min))
+      .collect()
+  };
+  let with_params = |params: &[f64]| {
+    let mut point = spdc.clone();
+    for ((field, _, _), value) in fields.iter().zip(params) {
+      (field.set)(&mut point, *value);
+    }
+    point
+  };
+  let start = fields
+    .iter()
+    .map(|(field, min, max)| ((field.get)(spdc) - min) / (max - min))
+    // fields without a value (like disabled poling) start mid-way
+    .map(|u| if u.is_nan() { 0.5 } else { u })
+    .collect();
+
+  let mut trace: Vec<(Vec<f64>, f64)> = Vec::new();
+  let best = py.allow_threads(|| {
+    nelder_mead::<PyKindError, _>(
+      |u| {
+        let params = to_params(u);
+        let value = evaluate_metric(&with_params(&params), metric, range, integrator)?;
+        trace.push((params, value));
+        Ok(-value)
+      },
+      start,
+      max_iterations,
+      tolerance,
+    )
+  });
+  let (best, _) = best?;
+
+  let n_fields = fields.len();
+  let dict = PyDict::new_bound(py);
+  let names: Vec<&str> = fields.iter().map(|(field, _, _)| field.name).collect();
+  dict.set_item("fields", names)?;
+  dict.set_item(
+    "params",
+    to_shaped_pyarray(
+      py,
+      trace.iter().flat_map(|(p, _)| p.clone()).collect(),
+      vec![trace.len(), n_fields],
+    )?,
+  )?;
+  dict.set_item(
+    "values",
+    PyArray1::from_vec_bound(py, trace.iter().map(|(_, v)| *v).collect()),
+  )?;
+  Ok((with_params(&to_params(&best)), dict))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn minimize(f: impl Fn(&[f64]) -> f64, x0: Vec<f64>) -> (Vec<f64>, f64) {
+    nelder_mead::<(), _>(|x| Ok(f(x)), x0, 500, 1e-10).unwrap()
+  }
+
+  #[test]
+  fn finds_interior_minimum() {
+    let (x, value) = minimize(
+      |x| (x[0] - 0.3).powi(2) + 2. * (x[1] - 0.7).powi(2),
+      vec![0.9, 0.1],
+    );
+    assert!((x[0] - 0.3).abs() < 1e-4, "{:?}", x);
+    assert!((x[1] - 0.7).abs() < 1e-4, "{:?}", x);
+    assert!(value < 1e-8);
+  }
+
+  #[test]
+  fn stays_within_bounds() {
+    let (x, _) = minimize(
+      |x| (x[0] - 2.).powi(2) + (x[1] + 1.).powi(2),
+      vec![0.5, 0.5],
+    );
+    assert!((x[0] - 1.).abs() < 1e-4, "{:?}", x);
+    assert!(x[1].abs() < 1e-4, "{:?}", x);
+  }
+}
@@ -0,0 +1,704 @@
+use crate::*;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::PyDict;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use spdcalc::dim::f64prefixes::NANO;
+use spdcalc::dim::ucum::{Hertz, HZ, M, RAD};
+use spdcalc::math::Integrator;
+use spdcalc::utils::{frequency_to_vacuum_wavelength, vacuum_wavelength_to_frequency};
+use spdcalc::{
+  Complex, Efficiencies, Frequency, FrequencySpace, IntoSignalIdlerIterator, JSIUnits, JsiNorm,
+  JsiSinglesNorm, PerMeter3, PerMeter4, SPDCError, Time,
+};
+
+/// The spectral shape of the pump
+///
+/// The analytic shapes take their width from the pump bandwidth of the setup.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(try_from = "PumpSpectrumConfig", into = "PumpSpectrumConfig")]
+pub(crate) enum PumpSpectrum {
+  /// Gaussian with an intensity FWHM of the pump bandwidth (as in spdcalc)
+  #[default]
+  Gaussian,
+  /// Hyperbolic secant squared intensity with a FWHM of the pump bandwidth
+  Sech2,
+  /// Flat top with a full width of the pump bandwidth
+  Rect,
+  /// Amplitudes sampled at vacuum wavelengths, sorted by wavelength
+  Sampled {
+    wavelengths_nm: Vec<f64>,
+    amplitudes: Vec<f64>,
+  },
+}
+
+/// How a pump spectrum is written in configs: either the name of a shape,
+/// or a table of samples
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum PumpSpectrumConfig {
+  Shape(String),
+  Sampled {
+    wavelengths_nm: Vec<f64>,
+    amplitudes: Vec<f64>,
+  },
+}
+
+impl TryFrom<PumpSpectrumConfig> for PumpSpectrum {
+  type Error = String;
+
+  fn try_from(config: PumpSpectrumConfig) -> Result<Self, Self::Error> {
+    match config {
+      PumpSpectrumConfig::Shape(name) => Self::shape(&name),
+      PumpSpectrumConfig::Sampled {
+        wavelengths_nm,
+        amplitudes,
+      } => Self::sampled(wavelengths_nm, amplitudes),
+    }
+  }
+}
+
+impl From<PumpSpectrum> for PumpSpectrumConfig {
+  fn from(spectrum: PumpSpectrum) -> Self {
+    match spectrum {
+      PumpSpectrum::Gaussian => Self::Shape("gaussian".into()),
+      PumpSpectrum::Sech2 => Self::Shape("sech2".into()),
+      PumpSpectrum::Rect => Self::Shape("rect".into()),
+      PumpSpectrum::Sampled {
+        wavelengths_nm,
+        amplitudes,
+      } => Self::Sampled {
+        wavelengths_nm,
+        amplitudes,
+      },
+    }
+  }
+}
+
+impl<'py> FromPyObject<'py> for PumpSpectrum {
+  fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+    let spectrum = if let Ok(name) = ob.extract::<String>() {
+      Self::shape(&name)
+    } else {
+      let table = ob.downcast::<PyDict>()?;
+      let column = |key: &str| -> PyResult<Vec<f64>> {
+        let values = table.get_item(key)?.ok_or_else(|| {
+          PyValueError::new_err(format!("Sampled pump spectrum is missing '{}'", key))
+        })?;
+        Ok(values.extract::<FloatArray<'_>>()?.map(|v| v))
+      };
+      Self::sampled(column("wavelengths_nm")?, column("amplitudes")?)
+    };
+    spectrum.map_err(PyValueError::new_err)
+  }
+}
+
+impl IntoPy<PyObject> for PumpSpectrum {
+  fn into_py(self, py: Python<'_>) -> PyObject {
+    match PumpSpectrumConfig::from(self) {
+      PumpSpectrumConfig::Shape(name) => name.into_py(py),
+      PumpSpectrumConfig::Sampled {
+        wavelengths_nm,
+        amplitudes,
+      } => {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("wavelengths_nm", wavelengths_nm).unwrap();
+        dict.set_item("amplitudes", amplitudes).unwrap();
+        dict.into_py(py)
+      }
+    }
+  }
+}
+
+impl PumpSpectrum {
+  /// An analytic shape by name
+  fn shape(name: &str) -> Result<Self, String> {
+    match name {
+      "gaussian" => Ok(Self::Gaussian),
+      "sech2" => Ok(Self::Sech2),
+      "rect" => Ok(Self::Rect),
+      other => Err(format!(
+        "Unknown pump spectrum '{}'. Expected one of: gaussian, sech2, rect, \
+         or a table of wavelengths_nm and amplitudes",
+        other
+      )),
+    }
+  }
+
+  /// A sampled spectrum, validated and sorted by wavelength
+  fn sampled(wavelengths_nm: Vec<f64>, amplitudes: Vec<f64>) -> Result<Self, String> {
+    if wavelengths_nm.len() != amplitudes.len() {
+      return Err(format!(
+        "Sampled pump spectrum has {} wavelengths but {} amplitudes",
+        wavelengths_nm.len(),
+        amplitudes.len()
+      ));
+    }
+    if wavelengths_nm.len() < 2 {
+      return Err("Sampled pump spectrum needs at least two samples".into());
+    }
+    if wavelengths_nm.iter().any(|l| !l.is_finite() || *l <= 0.)
+      || amplitudes.iter().any(|a| !a.is_finite())
+    {
+      return Err(
+        "Sampled pump spectrum must have positive wavelengths and finite amplitudes".into(),
+      );
+    }
+    if amplitudes.iter().all(|a| *a == 0.) {
+      return Err("Sampled pump spectrum must have a nonzero amplitude".into());
+    }
+    let mut samples: Vec<(f64, f64)> = wavelengths_nm.into_iter().zip(amplitudes).collect();
+    samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if samples.windows(2).any(|w| w[0].0 == w[1].0) {
+      return Err("Sampled pump spectrum has repeated wavelengths".into());
+    }
+    let (wavelengths_nm, amplitudes) = samples.into_iter().unzip();
+    Ok(Self::Sampled {
+      wavelengths_nm,
+      amplitudes,
+    })
+  }
+
+  pub(crate) fn is_gaussian(&self) -> bool {
+    *self == PumpSpectrum::Gaussian
+  }
+
+  /// The largest magnitude of the amplitude, which the pump spectrum threshold
+  /// is relative to
+  fn peak(&self) -> f64 {
+    match self {
+      PumpSpectrum::Sampled { amplitudes, .. } => {
+        amplitudes.iter().fold(0., |peak, a| a.abs().max(peak))
+      }
+      _ => 1.,
+    }
+  }
+
+  /// The spectral amplitude at a pump angular frequency
+  pub(crate) fn amplitude(&self, omega: Frequency, spdc: &::spdcalc::SPDC) -> f64 {
+    let detuning = *((omega - spdc.pump.frequency()) / (RAD * HZ));
+    match self {
+      PumpSpectrum::Gaussian => ::spdcalc::pump_spectral_amplitude(omega, spdc),
+      PumpSpectrum::Sech2 => 1. / (detuning / sech2_width(spdc)).cosh(),
+      PumpSpectrum::Rect => {
+        if detuning.abs() <= 0.5 * bandwidth_rad_per_s(spdc) {
+          1.
+        } else {
+          0.
+        }
+      }
+      PumpSpectrum::Sampled {
+        wavelengths_nm,
+        amplitudes,
+      } => {
+        let lambda_nm = *(frequency_to_vacuum_wavelength(omega) / (NANO * M));
+        interpolate(wavelengths_nm, amplitudes, lambda_nm)
+      }
+    }
+  }
+
+  /// The integral of the squared amplitude over angular frequency (rad/s)
+  fn power(&self, spdc: &::spdcalc::SPDC) -> f64 {
+    match self {
+      PumpSpectrum::Gaussian => gaussian_power(spdc),
+      PumpSpectrum::Sech2 => 2. * sech2_width(spdc),
+      PumpSpectrum::Rect => bandwidth_rad_per_s(spdc),
+      PumpSpectrum::Sampled {
+        wavelengths_nm,
+        amplitudes,
+      } => {
+        // trapezoids in frequency, subdividing the (linear in wavelength) segments
+        const SUBDIVISIONS: usize = 32;
+        let omega =
+          |lambda_nm: f64| *(vacuum_wavelength_to_frequency(lambda_nm * NANO * M) / (RAD * HZ));
+        let lambdas: Vec<f64> = wavelengths_nm
+          .windows(2)
+          .flat_map(|w| {
+            (0..SUBDIVISIONS).map(move |k| w[0] + (w[1] - w[0]) * k as f64 / SUBDIVISIONS as f64)
+          })
+          .chain(wavelengths_nm.last().copied())
+          .collect();
+        lambdas
+          .windows(2)
+          .map(|w| {
+            let (a, b) = (
+              interpolate(wavelengths_nm, amplitudes, w[0]),
+              interpolate(wavelengths_nm, amplitudes, w[1]),
+            );
+            0.5 * (a * a + b * b) * (omega(w[0]) - omega(w[1])).abs()
+          })
+          .sum()
+      }
+    }
+  }
+}
+
+/// The pump bandwidth as an angular frequency FWHM (rad/s)
+fn bandwidth_rad_per_s(spdc: &::spdcalc::SPDC) -> f64 {
+  let lambda_p = spdc.pump.vacuum_wavelength();
+  let high = vacuum_wavelength_to_frequency(lambda_p - 0.5 * spdc.pump_bandwidth);
+  let low = vacuum_wavelength_to_frequency(lambda_p + 0.5 * spdc.pump_bandwidth);
+  *((high - low) / (RAD * HZ))
+}
+
+/// The width parameter of a sech² intensity with a FWHM of the pump bandwidth
+fn sech2_width(spdc: &::spdcalc::SPDC) -> f64 {
+  bandwidth_rad_per_s(spdc) / (2. * 2f64.sqrt().acosh())
+}
+
+/// The integral of the squared gaussian amplitude, which the spdcalc
+/// normalization assumes
+fn gaussian_power(spdc: &::spdcalc::SPDC) -> f64 {
+  let width = ::spdcalc::fwhm_to_spectral_width(spdc.pump.vacuum_wavelength(), spdc.pump_bandwidth);
+  *(width / (RAD * HZ)) * (std::f64::consts::PI / 2.).sqrt()
+}
+
+/// Linearly interpolate sorted samples, which are zero outside their range
+fn interpolate(x: &[f64], y: &[f64], at: f64) -> f64 {
+  if at < x[0] || at > x[x.len() - 1] {
+    return 0.;
+  }
+  let k = x.partition_point(|&x| x <= at).clamp(1, x.len() - 1);
+  let t = (at - x[k - 1]) / (x[k] - x[k - 1]);
+  y[k - 1] + t * (y[k] - y[k - 1])
+}
+
+/// Joint spectrum of a setup pumped with a non-gaussian spectrum
+///
+/// This follows the spdcalc calculation, with the pump amplitude replaced and
+/// the normalization rescaled so the spectrum carries the same pump power.
+#[derive(Debug, Clone)]
+pub(crate) struct ShapedJointSpectrum {
+  spdc: ::spdcalc::SPDC,
+  pump: PumpSpectrum,
+  integrator: Integrator,
+  power_scale: f64,
+  jsa_center: f64,
+  jsi_singles_center: f64,
+}
+
+impl ShapedJointSpectrum {
+  pub(crate) fn new(spdc: ::spdcalc::SPDC, pump: PumpSpectrum, integrator: Integrator) -> Self {
+    let optimal = spdc
+      .clone()
+      .try_as_optimum()
+      .unwrap_or_else(|_| spdc.clone());
+    let center = Self::unnormalized(optimal, pump.clone(), integrator);
+    let (ws, wi) = (
+      center.spdc.signal.frequency(),
+      center.spdc.idler.frequency(),
+    );
+    Self {
+      jsa_center: center.jsa(ws, wi).norm(),
+      jsi_singles_center: *(center.jsi_singles(ws, wi) / JSIUnits::new(1.)),
+      ..Self::unnormalized(spdc, pump, integrator)
+    }
+  }
+
+  fn unnormalized(spdc: ::spdcalc::SPDC, pump: PumpSpectrum, integrator: Integrator) -> Self {
+    Self {
+      power_scale: gaussian_power(&spdc) / pump.power(&spdc),
+      spdc,
+      pump,
+      integrator,
+      jsa_center: 1.,
+      jsi_singles_center: 1.,
+    }
+  }
+
+  /// The pump amplitude for a signal-idler pair, or `None` where the JSA is
+  /// taken to be zero (as in spdcalc)
+  fn pump_amplitude(&self, omega_s: Frequency, omega_i: Frequency) -> Option<f64> {
+    let omega_p = self.spdc.pump.frequency();
+    let invalid = *(omega_s / (RAD * HZ)) <= 0.
+      || *(omega_i / (RAD * HZ)) <= 0.
+      || omega_s > omega_p
+      || omega_i > omega_p
+      || ((omega_s - omega_i) / omega_p).abs() > 0.75;
+    let alpha = self.pump.amplitude(omega_s + omega_i, &self.spdc);
+    // sampled amplitudes are in arbitrary units, so compare relative to the peak
+    if invalid || alpha.abs() < self.spdc.pump_spectrum_threshold * self.pump.peak() {
+      None
+    } else {
+      Some(alpha)
+    }
+  }
+
+  pub(crate) fn jsa(&self, omega_s: Frequency, omega_i: Frequency) -> Complex<f64> {
+    self
+      .pump_amplitude(omega_s, omega_i)
+      .map_or(Complex::new(0., 0.), |alpha| {
+        let f =
+          *(::spdcalc::phasematch_fiber_coupling(omega_s, omega_i, &self.spdc, self.integrator)
+            / PerMeter4::new(1.));
+        let n = *(::spdcalc::jsi_normalization(omega_s, omega_i, &self.spdc) / JsiNorm::new(1.));
+        (n * self.power_scale).sqrt() * alpha * f
+      })
+  }
+
+  pub(crate) fn jsi_singles(&self, omega_s: Frequency, omega_i: Frequency) -> JSIUnits<f64> {
+    JSIUnits::new(self.pump_amplitude(omega_s, omega_i).map_or(0., |alpha| {
+      let fs = *(::spdcalc::phasematch_singles_fiber_coupling(
+        omega_s,
+        omega_i,
+        &self.spdc,
+        self.integrator,
+      ) / PerMeter3::new(1.));
+      let n = *(::spdcalc::jsi_singles_normalization(omega_s, omega_i, &self.spdc)
+        / JsiSinglesNorm::new(1.));
+      n * self.power_scale * alpha * alpha * fs
+    }))
+  }
+}
+
+/// Joint spectrum of a setup, using spdcalc directly for a gaussian pump
+#[derive(Debug, Clone)]
+pub(crate) enum Spectrum {
+  Gaussian(::spdcalc::JointSpectrum),
+  Shaped(ShapedJointSpectrum),
+}
+
+impl Spectrum {
+  pub(crate) fn new(spdc: &SPDC, integrator: Integrator) -> Self {
+    if spdc.1.is_gaussian() {
+      Spectrum::Gaussian(spdc.0.joint_spectrum(integrator))
+    } else {
+      Spectrum::Shaped(ShapedJointSpectrum::new(
+        spdc.0.clone(),
+        spdc.1.clone(),
+        integrator,
+      ))
+    }
+  }
+
+  pub(crate) fn jsa(&self, omega_s: Frequency, omega_i: Frequency) -> Complex<f64> {
+    match self {
+      Spectrum::Gaussian(s) => s.jsa(omega_s, omega_i),
+      Spectrum::Shaped(s) => s.jsa(omega_s, omega_i),
+    }
+  }
+
+  pub(crate) fn jsa_normalized(&self, omega_s: Frequency, omega_i: Frequency) -> Complex<f64> {
+    match self {
+      Spectrum::Gaussian(s) => s.jsa_normalized(omega_s, omega_i),
+      Spectrum::Shaped(s) => s.jsa(omega_s, omega_i) / s.jsa_center,
+    }
+  }
+
+  pub(crate) fn jsi(&self, omega_s: Frequency, omega_i: Frequency) -> JSIUnits<f64> {
+    match self {
+      Spectrum::Gaussian(s) => s.jsi(omega_s, omega_i),
+      Spectrum::Shaped(s) => JSIUnits::new(s.jsa(omega_s, omega_i).norm_sqr()),
+    }
+  }
+
+  pub(crate) fn jsi_normalized(&self, omega_s: Frequency, omega_i: Frequency) -> f64 {
+    match self {
+      Spectrum::Gaussian(s) => s.jsi_normalized(omega_s, omega_i),
+      Spectrum::Shaped(s) => s.jsa(omega_s, omega_i).norm_sqr() / s.jsa_center.powi(2),
+    }
+  }
+
+  pub(crate) fn jsi_singles(&self, omega_s: Frequency, omega_i: Frequency) -> JSIUnits<f64> {
+    match self {
+      Spectrum::Gaussian(s) => s.jsi_singles(omega_s, omega_i),
+      Spectrum::Shaped(s) => s.jsi_singles(omega_s, omega_i),
+    }
+  }
+
+  pub(crate) fn jsi_singles_normalized(&self, omega_s: Frequency, omega_i: Frequency) -> f64 {
+    match self {
+      Spectrum::Gaussian(s) => s.jsi_singles_normalized(omega_s, omega_i),
+      Spectrum::Shaped(s) => {
+        *(s.jsi_singles(omega_s, omega_i) / JSIUnits::new(1.)) / s.jsi_singles_center
+      }
+    }
+  }
+
+  pub(crate) fn jsa_range<T: IntoSignalIdlerIterator>(&self, range: T) -> Vec<Complex<f64>> {
+    self.map_range(range, Self::jsa)
+  }
+
+  pub(crate) fn jsa_normalized_range<T: IntoSignalIdlerIterator>(
+    &self,
+    range: T,
+  ) -> Vec<Complex<f64>> {
+    self.map_range(range, Self::jsa_normalized)
+  }
+
+  pub(crate) fn jsi_range<T: IntoSignalIdlerIterator>(&self, range: T) -> Vec<JSIUnits<f64>> {
+    self.map_range(range, Self::jsi)
+  }
+
+  pub(crate) fn jsi_normalized_range<T: IntoSignalIdlerIterator>(&self, range: T) -> Vec<f64> {
+    self.map_range(range, Self::jsi_normalized)
+  }
+
+  pub(crate) fn jsi_singles_range<T: IntoSignalIdlerIterator>(
+    &self,
+    range: T,
+  ) -> Vec<JSIUnits<f64>> {
+    self.map_range(range, Self::jsi_singles)
+  }
+
+  pub(crate) fn jsi_singles_normalized_range<T: IntoSignalIdlerIterator>(
+    &self,
+    range: T,
+  ) -> Vec<f64> {
+    self.map_range(range, Self::jsi_singles_normalized)
+  }
+
+  pub(crate) fn schmidt_number<R: Into<FrequencySpace>>(&self, range: R) -> Result<f64, SPDCError> {
+    ::spdcalc::math::schmidt_number(self.jsa_range(range.into()))
+  }
+
+  fn map_range<T, V, F>(&self, range: T, f: F) -> Vec<V>
+  where
+    T: IntoSignalIdlerIterator,
+    V: Send,
+    F: Fn(&Self, Frequency, Frequency) -> V + Sync + Send,
+  {
+    range
+      .into_signal_idler_par_iterator()
+      .map(|(ws, wi)| f(self, ws, wi))
+      .collect()
+  }
+}
+
+/// Sum the JSI (or singles JSI) of a spectrum over a range into a count rate
+fn counts(
+  spdc: &::spdcalc::SPDC,
+  range: FrequencySpace,
+  jsi: impl Fn(Frequency, Frequency) -> JSIUnits<f64> + Sync + Send,
+) -> Hertz<f64> {
+  let (dws, dwi) = range.steps().division_widths();
+  let dw2 = dws * dwi;
+  ::spdcalc::get_counts_correction(spdc)
+    * range
+      .as_steps()
+      .into_par_iter()
+      .map(|(ws, wi)| jsi(ws, wi) * dw2)
+      .sum::<Hertz<f64>>()
+}
+
+/// Coincidence rate over a range, respecting the pump spectrum
+pub(crate) fn counts_coincidences(
+  spdc: &SPDC,
+  range: FrequencySpace,
+  integrator: Integrator,
+) -> Hertz<f64> {
+  if spdc.1.is_gaussian() {
+    return spdc.0.counts_coincidences(range, integrator);
+  }
+  let s = ShapedJointSpectrum::new(spdc.0.clone(), spdc.1.clone(), integrator);
+  counts(&spdc.0, range, |ws, wi| {
+    JSIUnits::new(s.jsa(ws, wi).norm_sqr())
+  })
+}
+
+/// Signal singles rate over a range, respecting the pump spectrum
+pub(crate) fn counts_singles_signal(
+  spdc: &SPDC,
+  range: FrequencySpace,
+  integrator: Integrator,
+) -> Hertz<f64> {
+  if spdc.1.is_gaussian() {
+    return spdc.0.counts_singles_signal(range, integrator);
+  }
+  let s = ShapedJointSpectrum::new(spdc.0.clone(), spdc.1.clone(), integrator);
+  counts(&spdc.0, range, |ws, wi| s.jsi_singles(ws, wi))
+}
+
+/// Idler singles rate over a range, respecting the pump spectrum
+pub(crate) fn counts_singles_idler(
+  spdc: &SPDC,
+  range: FrequencySpace,
+  integrator: Integrator,
+) -> Hertz<f64> {
+  if spdc.1.is_gaussian() {
+    return spdc.0.counts_singles_idler(range, integrator);
+  }
+  let swapped = spdc.0.clone().with_swapped_signal_idler();
+  let s = ShapedJointSpectrum::new(swapped, spdc.1.clone(), integrator);
+  counts(&spdc.0, range, |ws, wi| s.jsi_singles(wi, ws))
+}
+
+/// Efficiencies and rates over a range, respecting the pump spectrum
+pub(crate) fn efficiencies(
+  spdc: &SPDC,
+  range: FrequencySpace,
+  integrator: Integrator,
+) -> Efficiencies {
+  if spdc.1.is_gaussian() {
+    return spdc.0.efficiencies(range, integrator);
+  }
+  ::spdcalc::efficiencies_from_counts(
+    counts_coincidences(spdc, range, integrator),
+    counts_singles_signal(spdc, range, integrator),
+    counts_singles_idler(spdc, range, integrator),
+  )
+}
+
+/// The JSA over a range, and with signal and idler exchanged
+fn jsa_and_swapped(
+  spectrum: &Spectrum,
+  range: FrequencySpace,
+) -> (Vec<Complex<f64>>, Vec<Complex<f64>>) {
+  let jsa = spectrum.jsa_range(range);
+  let swapped = range
+    .as_steps()
+    .into_par_iter()
+    .map(|(ws, wi)| spectrum.jsa(wi, ws))
+    .collect();
+  (jsa, swapped)
+}
+
+/// HOM time delay and visibility, respecting the pump spectrum
+pub(crate) fn hom_visibility(
+  spdc: &SPDC,
+  range: FrequencySpace,
+  integrator: Integrator,
+) -> (Time, f64) {
+  if spdc.1.is_gaussian() {
+    return spdc.0.hom_visibility(range, integrator);
+  }
+  let (jsa, swapped) = jsa_and_swapped(&Spectrum::new(spdc, integrator), range);
+  let delta_t = ::spdcalc::hom_time_delay(&spdc.0);
+  let min_rate = ::spdcalc::hom_rate(range, &jsa, &swapped, delta_t, None);
+  (delta_t, (0.5 - min_rate) / 0.5)
+}
+
+/// HOM coincidence rate for a series of time delays, respecting the pump spectrum
+pub(crate) fn hom_rate_series(
+  spdc: &SPDC,
+  time_delays: Vec<Time>,
+  range: FrequencySpace,
+  integrator: Integrator,
+) -> Vec<f64> {
+  if spdc.1.is_gaussian() {
+    return spdc.0.hom_rate_series(time_delays, range, integrator);
+  }
+  let (jsa, swapped) = jsa_and_swapped(&Spectrum::new(spdc, integrator), range);
+  ::spdcalc::hom_rate_series(range, &jsa, &swapped, time_delays)
+}
+
+/// Two-source HOM is calculated by spdcalc, which only knows the gaussian pump
+pub(crate) fn require_gaussian(spdc: &SPDC, calculation: &str) -> PyResult<()> {
+  if spdc.1.is_gaussian() {
+    Ok(())
+  } else {
+    Err(PyValueError::new_err(format!(
+      "{} is only available for a gaussian pump spectrum",
+      calculation
+    )))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn shaped(pump: PumpSpectrum) -> SPDC {
+    let mut spdc = SPDC::default();
+    spdc.1 = pump;
+    spdc
+  }
+
+  #[test]
+  fn analytic_shapes_have_the_bandwidth_as_fwhm() {
+    let spdc = SPDC::default().0;
+    let omega_p = spdc.pump.frequency();
+    let half_width = 0.5 * bandwidth_rad_per_s(&spdc) * RAD * HZ;
+    for pump in [PumpSpectrum::Gaussian, PumpSpectrum::Sech2] {
+      let intensity = pump.amplitude(omega_p + half_width, &spdc).powi(2);
+      assert!((intensity - 0.5).abs() < 1e-3, "{:?}: {}", pump, intensity);
+      assert!((pump.amplitude(omega_p, &spdc) - 1.).abs() < 1e-12);
+    }
+    assert_eq!(
+      PumpSpectrum::Rect.amplitude(omega_p + 0.99 * half_width, &spdc),
+      1.
+    );
+    assert_eq!(
+      PumpSpectrum::Rect.amplitude(omega_p + 1.01 * half_width, &spdc),
+      0.
+    );
+  }
+
+  #[test]
+  fn sampled_gaussian_matches_gaussian() {
+    let spdc = SPDC::default();
+    let (lambda_p, bandwidth) = (spdc.pump_wavelength_nm(), spdc.pump_bandwidth_nm());
+    let omega = |lambda_nm: f64| vacuum_wavelength_to_frequency(lambda_nm * NANO * M);
+    let wavelengths_nm: Vec<f64> = (0..=400)
+      .map(|k| lambda_p + bandwidth * (0.02 * k as f64 - 4.))
+      .collect();
+    let amplitudes = wavelengths_nm
+      .iter()
+      .map(|l| PumpSpectrum::Gaussian.amplitude(omega(*l), &spdc.0))
+      .collect();
+    let sampled = PumpSpectrum::sampled(wavelengths_nm, amplitudes).unwrap();
+    let ratio = sampled.power(&spdc.0) / PumpSpectrum::Gaussian.power(&spdc.0);
+    assert!((ratio - 1.).abs() < 1e-3, "{}", ratio);
+
+    let range = spdc.0.optimum_range(5);
+    let integrator = Integrator::default();
+    let expected = counts_coincidences(&spdc, range, integrator);
+    let actual = counts_coincidences(&shaped(sampled), range, integrator);
+    assert!((*(actual / expected) - 1.).abs() < 1e-3);
+  }
+
+  #[test]
+  fn sampled_amplitudes_are_in_arbitrary_units() {
+    let spdc = SPDC::default();
+    let (lambda_p, bandwidth) = (spdc.pump_wavelength_nm(), spdc.pump_bandwidth_nm());
+    let wavelengths_nm = vec![lambda_p - bandwidth, lambda_p, lambda_p + bandwidth];
+    let sampled = |scale: f64| {
+      let amplitudes = vec![0.2 * scale, scale, 0.2 * scale];
+      shaped(PumpSpectrum::sampled(wavelengths_nm.clone(), amplitudes).unwrap())
+    };
+    let range = spdc.0.optimum_range(5);
+    let integrator = Integrator::default();
+    let expected = counts_coincidences(&sampled(1.), range, integrator);
+    for scale in [1e-3, 1e4] {
+      let actual = counts_coincidences(&sampled(scale), range, integrator);
+      assert!((*(actual / expected) - 1.).abs() < 1e-9, "{}", scale);
+    }
+    assert!(PumpSpectrum::sampled(wavelengths_nm.clone(), vec![0.; 3]).is_err());
+  }
+
+  #[test]
+  fn config_round_trip() {
+    let spdc = shaped(PumpSpectrum::Sech2);
+    let yaml = spdc.to_yaml(false).map_err(|e| e.0).unwrap();
+    assert!(yaml.contains("spectrum: sech2"));
+    assert_eq!(SPDC::from_yaml(&yaml).unwrap().1, PumpSpectrum::Sech2);
+
+    let sampled = PumpSpectrum::sampled(vec![776., 774., 775.], vec![0., 0., 1.]).unwrap();
+    assert_eq!(
+      sampled,
+      PumpSpectrum::Sampled {
+        wavelengths_nm: vec![774., 775., 776.],
+        amplitudes: vec![0., 1., 0.],
+      }
+    );
+    let json = shaped(sampled.clone())
+      .to_json(false)
+      .map_err(|e| e.0)
+      .unwrap();
+    assert_eq!(SPDC::from_json(&json).unwrap().1, sampled);
+
+    // configs without a spectrum are gaussian, and are written unchanged
+    let spdc = SPDC::default();
+    assert!(!spdc
+      .to_yaml(false)
+      .map_err(|e| e.0)
+      .unwrap()
+      .contains(" spectrum:"));
+    assert_eq!(
+      SPDC::from_yaml(&spdc.to_yaml(false).map_err(|e| e.0).unwrap())
+        .unwrap()
+        .1,
+      PumpSpectrum::Gaussian
+    );
+  }
+}
@@ -0,0 +1,527 @@
+use crate::*;
+use ::spdcalc::{PMType, SPDCConfig};
+use serde_json::{json, Value};
+
+/// JSON Schema dialect of the generated schema
+const DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Apodization kinds with a single number as parameter
+const APODIZATION_WINDOWS: [&str; 6] = [
+  "Bartlett", "Blackman", "Connes", "Cosine", "Hamming", "Welch",
+];
+
+/// A kind name as accepted by `ApodizationConfig` (title or lower case)
+fn apodization_kind(name: &str) -> Value {
+  json!({ "enum": [name, name.to_lowercase()] })
+}
+
+/// Refractive index expressions of BBO, as an example of a custom crystal
+const EXAMPLE_NO: &str = "sqrt(2.7359+0.01878/(l^2-0.01822)-0.01354*l^2) - 9.3e-6 * T";
+const EXAMPLE_NE: &str = "sqrt(2.3753+0.01224/(l^2-0.01667)-0.01516*l^2) - 16.6e-6 * T";
+
+/// The default value of a field, from the default spdcalc config
+fn default_of(defaults: &Value, section: &str, field: &str) -> Value {
+  defaults[section][field].clone()
+}
+
+/// A signal or idler beam section
+fn beam_schema(beam: &str) -> Value {
+  json!({
+    "type": "object",
+    "description": format!("The {} beam. One of theta_deg or theta_external_deg is required.", beam),
+    "properties": {
+      "wavelength_nm": { "type": "number", "exclusiveMinimum": 0, "description": "Vacuum wavelength in nanometers" },
+      "phi_deg": { "type": "number", "default": 0, "description": "Azimuthal angle in degrees" },
+      "theta_deg": { "type": ["number", "null"], "description": "Polar angle inside the crystal in degrees" },
+      "theta_external_deg": { "type": ["number", "null"], "description": "Polar angle outside the crystal in degrees" },
+      "waist_um": { "$ref": "#/$defs/waist" },
+      "waist_position_um": {
+        "$ref": "#/$defs/number_or_auto",
+        "default": "auto",
+        "description": "Position of the waist in micrometers, or \"auto\" for the optimum position"
+      }
+    },
+    "required": ["wavelength_nm", "waist_um"],
+    "anyOf": [
+      { "properties": { "theta_deg": { "type": "number" } }, "required": ["theta_deg"] },
+      { "properties": { "theta_external_deg": { "type": "number" } }, "required": ["theta_external_deg"] }
+    ],
+    "additionalProperties": false
+  })
+}
+
+/// The apodization of periodic poling, tagged by `kind` with its `parameter`
+fn apodization_schema() -> Value {
+  let mut variants = vec![
+    json!({
+      "properties": { "kind": { "enum": ["Off", "off", "none", "None"] } },
+      "required": ["kind"]
+    }),
+    json!({
+      "properties": {
+        "kind": apodization_kind("Gaussian"),
+        "parameter": {
+          "type": "object",
+          "properties": { "fwhm_um": { "type": "number", "exclusiveMinimum": 0 } },
+          "required": ["fwhm_um"],
+          "additionalProperties": false
+        }
+      },
+      "required": ["kind", "parameter"]
+    }),
+    json!({
+      "properties": {
+        "kind": apodization_kind("Interpolate"),
+        "parameter": { "type": "array", "items": { "type": "number" }, "minItems": 1 }
+      },
+      "required": ["kind", "parameter"]
+    }),
+  ];
+  variants.extend(APODIZATION_WINDOWS.iter().map(|name| {
+    json!({
+      "properties": { "kind": apodization_kind(name), "parameter": { "type": "number" } },
+      "required": ["kind", "parameter"]
+    })
+  }));
+  json!({
+    "type": "object",
+    "description": "Apodization of the poling, as a kind and its parameter",
+    "properties": { "kind": {}, "parameter": {} },
+    "additionalProperties": false,
+    "oneOf": variants,
+    "default": { "kind": "Off" }
+  })
+}
+
+/// The JSON Schema of SPDC configs
+///
+/// Follows the fields of `SPDCConfig`, with the defaults of its default config,
+/// and includes the extensions of these bindings (elliptical waists and
+/// `pump.spectrum`). Unknown fields are rejected to catch typos, although
+/// spdcalc itself ignores them. The tests check that the parser accepts every
+/// variant and example, and gives the documented defaults.
+pub(crate) fn schema() -> Value {
+  let defaults = serde_json::to_value(SPDCConfig::default()).expect("configs always serialize");
+  let crystal_ids: Vec<&str> = ::spdcalc::CrystalType::get_all_meta()
+    .iter()
+    .map(|meta| meta.id)
+    .collect();
+  let pm_types: Vec<String> = [
+    PMType::Type0_o_oo,
+    PMType::Type0_e_ee,
+    PMType::Type1_e_oo,
+    PMType::Type2_e_eo,
+    PMType::Type2_e_oe,
+  ]
+  .iter()
+  .map(PMType::to_string)
+  .collect();
+
+  json!({
+    "$schema": DIALECT,
+    "title": "SPDC config",
+    "description": "Configuration of an SPDC setup, as accepted by SPDC.from_yaml and SPDC.from_json",
+    "type": "object",
+    "properties": {
+      "crystal": {
+        "type": "object",
+        "properties": {
+          "kind": {
+            "description": "A built-in crystal id, or refractive index expressions of the wavelength l (um) and temperature T (C): no and ne for uniaxial, nx, ny and nz for biaxial crystals",
+            "anyOf": [
+              { "enum": crystal_ids },
+              {
+                "type": "object",
+                "properties": { "no": { "type": "string" }, "ne": { "type": "string" } },
+                "required": ["no", "ne"],
+                "additionalProperties": false,
+                "examples": [{ "no": EXAMPLE_NO, "ne": EXAMPLE_NE }]
+              },
+              {
+                "type": "object",
+                "properties": { "nx": { "type": "string" }, "ny": { "type": "string" }, "nz": { "type": "string" } },
+                "required": ["nx", "ny", "nz"],
+                "additionalProperties": false,
+                "examples": [{ "nx": EXAMPLE_NO, "ny": EXAMPLE_NO, "nz": EXAMPLE_NE }]
+              }
+            ]
+          },
+          "pm_type": {
+            "type": "string",
+            "description": "Phasematching type. Many formats are accepted, e.g. \"Type2_e_eo\", \"type 2 e-eo\", \"e->eo\" or \"eeo\"",
+            "examples": pm_types
+          },
+          "phi_deg": { "type": "number", "default": default_of(&defaults, "crystal", "phi_deg") },
+          "theta_deg": {
+            "$ref": "#/$defs/number_or_auto",
+            "default": "auto",
+            "description": "Crystal angle in degrees, or \"auto\" for the optimum angle (only without periodic poling)"
+          },
+          "length_um": { "type": "number", "exclusiveMinimum": 0 },
+          "temperature_c": { "type": "number", "exclusiveMinimum": -273.15 },
+          "counter_propagation": { "type": "boolean", "default": false }
+        },
+        "required": ["kind", "pm_type", "length_um", "temperature_c"],
+        "additionalProperties": false
+      },
+      "pump": {
+        "type": "object",
+        "properties": {
+          "wavelength_nm": { "type": "number", "exclusiveMinimum": 0 },
+          "waist_um": { "$ref": "#/$defs/waist" },
+          "bandwidth_nm": { "type": "number", "exclusiveMinimum": 0, "description": "Spectral FWHM in nanometers" },
+          "average_power_mw": { "type": "number", "minimum": 0 },
+          "spectrum_threshold": {
+            "type": ["number", "null"],
+            "default": default_of(&defaults, "pump", "spectrum_threshold"),
+            "description": "Relative pump amplitude below which the spectrum is neglected"
+          },
+          "spectrum": { "$ref": "#/$defs/pump_spectrum" }
+        },
+        "required": ["wavelength_nm", "waist_um", "bandwidth_nm", "average_power_mw"],
+        "additionalProperties": false
+      },
+      "signal": beam_schema("signal"),
+      "idler": {
+        "description": "The idler beam, or \"auto\" to derive it from the signal and pump",
+        "anyOf": [{ "const": "auto" }, beam_schema("idler")],
+        "default": "auto"
+      },
+      "periodic_poling": {
+        "description": "Periodic poling, or null for none",
+        "anyOf": [
+          { "type": "null" },
+          {
+            "type": "object",
+            "properties": {
+              "poling_period_um": {
+                "$ref": "#/$defs/number_or_auto",
+                "description": "Poling period in micrometers, or \"auto\" for the optimum period. A negative period reverses the sign of the poling that phasematches"
+              },
+              "apodization": apodization_schema()
+            },
+            "required": ["poling_period_um"],
+            "additionalProperties": false
+          }
+        ],
+        "default": null
+      },
+      "deff_pm_per_volt": { "type": "number", "description": "Effective nonlinear coefficient in pm/V" }
+    },
+    "required": ["crystal", "pump", "signal", "deff_pm_per_volt"],
+    "additionalProperties": false,
+    "$defs": {
+      "number_or_auto": { "anyOf": [{ "type": "number" }, { "const": "auto" }] },
+      "waist": {
+        "description": "Waist (1/e^2 radius of the intensity) in micrometers, or [x, y] for an elliptical waist",
+        "anyOf": [
+          { "type": "number", "exclusiveMinimum": 0 },
+          {
+            "type": "array",
+            "items": { "type": "number", "exclusiveMinimum": 0 },
+            "minItems": 2,
+            "maxItems": 2
+          }
+        ]
+      },
+      "pump_spectrum": {
+        "description": "Shape of the pump spectrum, with the pump bandwidth as its FWHM, or a sampled spectral amplitude",
+        "anyOf": [
+          { "enum": ["gaussian", "sech2", "rect"] },
+          {
+            "type": "object",
+            "properties": {
+              "wavelengths_nm": { "type": "array", "items": { "type": "number" }, "minItems": 2 },
+              "amplitudes": { "type": "array", "items": { "type": "number" }, "minItems": 2 }
+            },
+            "required": ["wavelengths_nm", "amplitudes"],
+            "additionalProperties": false
+          }
+        ],
+        "default": "gaussian"
+      }
+    }
+  })
+}
+
+/// Get the JSON Schema of SPDC configs
+///
+/// The schema describes the config format accepted by :meth:`SPDC.from_yaml` and
+/// :meth:`SPDC.from_json`, including ``"auto"`` values, ``idler: auto``,
+/// elliptical waists, pump spectra and the apodization variants. Use it to
+/// validate config files in editors or CI. It is stricter than the parser in
+/// rejecting unknown fields, which are otherwise ignored.
+///
+/// Returns
+/// -------
+/// `dict`
+///     The JSON Schema (draft 2020-12), ready for ``json.dump``
+#[pyfunction]
+pub(crate) fn config_schema(py: Python<'_>) -> PyResult<PyObject> {
+  let json = py.import_bound("json")?;
+  Ok(
+    json
+      .call_method1("loads", (schema().to_string(),))?
+      .unbind(),
+  )
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::config::Extensions;
+  use crate::diff::merge;
+
+  /// The property names of an object schema
+  fn properties(schema: &Value) -> Vec<&str> {
+    schema["properties"]
+      .as_object()
+      .unwrap()
+      .keys()
+      .map(String::as_str)
+      .collect()
+  }
+
+  /// Whether a field is in the required list of an object schema
+  fn is_required(schema: &Value, field: &str) -> bool {
+    schema["required"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .any(|f| f == field)
+  }
+
+  fn parses(config: &Value) -> bool {
+    serde_json::from_value::<SPDCConfig>(config.clone()).is_ok()
+  }
+
+  /// Whether the parser accepts the types of a config, including the extensions
+  fn accepts(config: &Value) -> Result<(), String> {
+    let mut value = serde_yaml::to_value(config).unwrap();
+    let mut problems = Vec::new();
+    Extensions::take(&mut value, &mut problems);
+    if let Some(problem) = problems.pop() {
+      return Err(problem.message);
+    }
+    serde_yaml::from_value::<SPDCConfig>(value)
+      .map(|_| ())
+      .map_err(|e| e.to_string())
+  }
+
+  /// Values described by a schema: its examples, or else one value of each
+  /// variant, type and (for objects) variant of each property
+  fn instances(schema: &Value, root: &Value) -> Vec<Value> {
+    if let Some(examples) = schema["examples"].as_array() {
+      return examples.clone();
+    }
+    if let Some(name) = schema["$ref"].as_str() {
+      let name = name.strip_prefix("#/$defs/").unwrap();
+      return instances(&root["$defs"][name], root);
+    }
+    if let Some(value) = schema.get("const") {
+      return vec![value.clone()];
+    }
+    if let Some(values) = schema["enum"].as_array() {
+      return values.clone();
+    }
+    let variants = schema["anyOf"].as_array().or(schema["oneOf"].as_array());
+    if let Some(variants) = variants {
+      let mut base = schema.clone();
+      let base_object = base.as_object_mut().unwrap();
+      base_object.remove("anyOf");
+      base_object.remove("oneOf");
+      return variants
+        .iter()
+        .flat_map(|variant| {
+          let mut variant_schema = base.clone();
+          merge(&mut variant_schema, variant.clone());
+          // a variant adds to the required fields
+          let required: Vec<Value> = [&base["required"], &variant["required"]]
+            .into_iter()
+            .filter_map(Value::as_array)
+            .flatten()
+            .cloned()
+            .collect();
+          variant_schema["required"] = required.into();
+          instances(&variant_schema, root)
+        })
+        .collect();
+    }
+    if let Some(types) = schema["type"].as_array() {
+      return types
+        .iter()
+        .flat_map(|t| {
+          let mut single = schema.clone();
+          single["type"] = t.clone();
+          instances(&single, root)
+        })
+        .collect();
+    }
+    match schema["type"].as_str() {
+      Some("null") => vec![Value::Null],
+      Some("boolean") => vec![json!(true), json!(false)],
+      Some("number") => {
+        let min = schema["exclusiveMinimum"]
+          .as_f64()
+          .map(|min| min + 1.)
+          .or(schema["minimum"].as_f64());
+        vec![json!(min.unwrap_or(1.))]
+      }
+      Some("array") => {
+        let len = schema["minItems"].as_u64().unwrap_or(1);
+        instances(&schema["items"], root)
+          .into_iter()
+          .map(|item| {
+            // distinct numbers, e.g. for sampled wavelengths
+            (0..len)
+              .map(|k| match item.as_f64() {
+                Some(x) => json!(x + k as f64),
+                None => item.clone(),
+              })
+              .collect()
+          })
+          .collect()
+      }
+      Some("object") => {
+        let properties = schema["properties"].as_object().unwrap();
+        let mut base = json!({});
+        for field in schema["required"].as_array().into_iter().flatten() {
+          let field = field.as_str().unwrap();
+          base[field] = instances(&properties[field], root).swap_remove(0);
+        }
+        let mut values = vec![base.clone()];
+        for (field, property) in properties {
+          for value in instances(property, root) {
+            let mut with = base.clone();
+            with[field] = value;
+            values.push(with);
+          }
+        }
+        values
+      }
+      Some(other) => panic!("no instances of type {}", other),
+      None => vec![],
+    }
+  }
+
+  #[test]
+  fn parser_accepts_every_variant() {
+    let schema = schema();
+    let configs = instances(&schema, &schema);
+    assert!(configs.len() > 50, "{}", configs.len());
+    for config in configs {
+      if let Err(e) = accepts(&config) {
+        panic!("{}: {}", e, config);
+      }
+    }
+  }
+
+  #[test]
+  fn defaults_match_the_parser() {
+    let build = |config: &Value| {
+      let value = serde_yaml::to_value(config).unwrap();
+      crate::config::parse_config_value(value).map(|spdc| spdc.config_value(true))
+    };
+    let schema = schema();
+    let config = serde_json::to_value(SPDC::default().config_value(true)).unwrap();
+    let mut poled = config.clone();
+    poled["periodic_poling"] = json!({ "poling_period_um": 10 });
+    let properties = &schema["properties"];
+    let sections = [
+      (None, &schema, &config),
+      (Some("crystal"), &properties["crystal"], &config),
+      (Some("pump"), &properties["pump"], &config),
+      (Some("signal"), &properties["signal"], &config),
+      (Some("idler"), &properties["idler"]["anyOf"][1], &config),
+      (
+        Some("periodic_poling"),
+        &properties["periodic_poling"]["anyOf"][1],
+        &poled,
+      ),
+    ];
+    let mut checked = 0;
+    for (section, section_schema, config) in sections {
+      for (field, property) in section_schema["properties"].as_object().unwrap() {
+        let Some(default) = property.get("default") else {
+          continue;
+        };
+        let path = section.map_or(field.clone(), |s| format!("{}.{}", s, field));
+        // the config with the field set to its default, and without the field
+        let (mut with, mut without) = (config.clone(), config.clone());
+        let (patch, fields) = match section {
+          Some(section) => (
+            json!({ section: { field: default } }),
+            &mut without[section],
+          ),
+          None => (json!({ field: default }), &mut without),
+        };
+        fields.as_object_mut().unwrap().remove(field);
+        merge(&mut with, patch);
+        let expected = build(&without).unwrap_or_else(|e| panic!("{}: {}", path, e.message));
+        let actual = build(&with).unwrap_or_else(|e| panic!("{}: {}", path, e.message));
+        assert_eq!(actual, expected, "{}", path);
+        checked += 1;
+      }
+    }
+    assert!(checked >= 10, "{}", checked);
+  }
+
+  #[test]
+  fn describes_every_config_field() {
+    let schema = schema();
+    let config = serde_json::to_value(SPDCConfig::default()).unwrap();
+    for (section, fields) in config.as_object().unwrap() {
+      assert!(
+        properties(&schema).contains(&section.as_str()),
+        "{}",
+        section
+      );
+      let Some(fields) = fields.as_object() else {
+        continue;
+      };
+      let section_schema = &schema["properties"][section];
+      for field in fields.keys() {
+        assert!(
+          properties(section_schema).contains(&field.as_str()),
+          "{}.{}",
+          section,
+          field
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn required_fields_match_the_parser() {
+    let schema = schema();
+    let mut config = serde_json::to_value(SPDCConfig::default()).unwrap();
+    // the default signal has both angles, either of which is enough
+    config["signal"]["theta_external_deg"] = Value::Null;
+    for section in ["crystal", "pump", "signal"] {
+      let section_schema = &schema["properties"][section];
+      for field in properties(section_schema) {
+        let mut without = config.clone();
+        if without[section]
+          .as_object_mut()
+          .unwrap()
+          .remove(field)
+          .is_none()
+        {
+          continue;
+        }
+        let required = is_required(section_schema, field);
+        assert_eq!(parses(&without), !required, "{}.{}", section, field);
+      }
+    }
+    for section in properties(&schema) {
+      let mut without = config.clone();
+      without.as_object_mut().unwrap().remove(section);
+      assert_eq!(
+        parses(&without),
+        !is_required(&schema, section),
+        "{}",
+        section
+      );
+    }
+  }
+}
@@ -1,5 +1,7 @@
 use crate::*;
+use numpy::{PyArray1, PyArrayDyn, PyArrayMethods};
 use pyo3::exceptions::PyValueError;
+use pyo3::types::{PyDict, PyType};
 use rayon::prelude::*;
 use spdcalc::{
   dim::ucum::{HZ, M, RAD},
@@ -21,16 +23,135 @@ pub(crate) enum SIRange {
   SumDiffFrequency(SumDiffFrequencySpace),
 }
 
+impl SIRange {
+  /// The shape of the values produced by iterating over this range
+  ///
+  /// Spaces are iterated row by row with the x-axis varying fastest, so they
+  /// map onto `(y_steps, x_steps)`. Arrays map onto one value per signal-idler pair.
+  pub(crate) fn shape(&self) -> Vec<usize> {
+    match self {
+      SIRange::FrequencySpace(fs) => {
+        let steps = fs.0.steps();
+        vec![steps.1 .2, steps.0 .2]
+      }
+      SIRange::FrequencyArray(fa) => vec![fa.0 .0.len() / 2],
+      SIRange::Wavelength(ws) => {
+        let steps = ws.0.steps();
+        vec![steps.1 .2, steps.0 .2]
+      }
+      SIRange::WavelengthArray(wa) => vec![wa.0 .0.len() / 2],
+      SIRange::SumDiffFrequency(sdfs) => {
+        let steps = sdfs.0.steps();
+        vec![steps.1 .2, steps.0 .2]
+      }
+    }
+  }
+
+  /// The signal (x) and idler (y) axis values of a frequency or wavelength space
+  ///
+  /// Values are in rad/s for frequency spaces and meters for wavelength spaces.
+  pub(crate) fn signal_idler_axes(&self) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    match self {
+      SIRange::FrequencySpace(fs) => Ok((fs.x_values(), fs.y_values())),
+      SIRange::Wavelength(ws) => Ok((ws.x_values(), ws.y_values())),
+      _ => Err(UnitError::new_err(
+        "Expected a FrequencySpace or WavelengthSpace with signal and idler axes",
+      )),
+    }
+  }
+
+  /// The name of the kind of range, as saved alongside computed grids
+  pub(crate) fn kind(&self) -> &'static str {
+    match self {
+      SIRange::FrequencySpace(_) => "frequency",
+      SIRange::FrequencyArray(_) => "frequency_array",
+      SIRange::Wavelength(_) => "wavelength",
+      SIRange::WavelengthArray(_) => "wavelength_array",
+      SIRange::SumDiffFrequency(_) => "sum_diff",
+    }
+  }
+
+  /// The x and y values of the range, as given by `x_values()` and `y_values()`
+  pub(crate) fn axes(&self) -> (Vec<f64>, Vec<f64>) {
+    match self {
+      SIRange::FrequencySpace(fs) => (fs.x_values(), fs.y_values()),
+      SIRange::FrequencyArray(fa) => (fa.x_values(), fa.y_values()),
+      SIRange::Wavelength(ws) => (ws.x_values(), ws.y_values()),
+      SIRange::WavelengthArray(wa) => (wa.x_values(), wa.y_values()),
+      SIRange::SumDiffFrequency(sdfs) => (sdfs.x_values(), sdfs.y_values()),
+    }
+  }
+
+  /// Reconstruct a range from its kind and its x and y values
+  ///
+  /// Spaces are recreated from the first and last values and the number of steps,
+  /// arrays from the signal-idler pairs.
+  pub(crate) fn from_axes(kind: &str, x: &[f64], y: &[f64]) -> PyResult<Self> {
+    let steps = |values: &[f64]| match values {
+      [first, .., last] => Ok((*first, *last, values.len())),
+      [only] => Ok((*only, *only, 1)),
+      [] => Err(PyValueError::new_err(
+        "Expected at least one value on each axis",
+      )),
+    };
+    let pairs = || -> PyResult<Vec<f64>> {
+      if x.len() != y.len() {
+        return Err(PyValueError::new_err(format!(
+          "Expected as many x values as y values for an array, got {} and {}",
+          x.len(),
+          y.len()
+        )));
+      }
+      Ok(x.iter().zip(y).flat_map(|(x, y)| [*x, *y]).collect())
+    };
+    Ok(match kind {
+      "frequency" => SIRange::FrequencySpace(FrequencySpace::new(steps(x)?, steps(y)?)),
+      "wavelength" => SIRange::Wavelength(WavelengthSpace::new(steps(x)?, steps(y)?)),
+      "sum_diff" => SIRange::SumDiffFrequency(SumDiffFrequencySpace::new(steps(x)?, steps(y)?)),
+      "frequency_array" => SIRange::FrequencyArray(FrequencyArray::new(FloatArray::List(pairs()?))?),
+      "wavelength_array" => SIRange::WavelengthArray(WavelengthArray::new(FloatArray::List(pairs()?))?),
+      other => {
+        return Err(PyValueError::new_err(format!(
+          "Unknown kind of range '{}'. Expected one of: frequency, wavelength, sum_diff, frequency_array, wavelength_array",
+          other
+        )))
+      }
+    })
+  }
+}
+
+impl IntoPy<PyObject> for SIRange {
+  fn into_py(self, py: Python<'_>) -> PyObject {
+    match self {
+      SIRange::FrequencySpace(fs) => fs.into_py(py),
+      SIRange::FrequencyArray(fa) => fa.into_py(py),
+      SIRange::Wavelength(ws) => ws.into_py(py),
+      SIRange::WavelengthArray(wa) => wa.into_py(py),
+      SIRange::SumDiffFrequency(sdfs) => sdfs.into_py(py),
+    }
+  }
+}
+
+/// Move values computed over an SIRange into a numpy array of the given shape
+/// without going through a python list
+pub(crate) fn to_shaped_pyarray<'py, T: numpy::Element>(
+  py: Python<'py>,
+  values: Vec<T>,
+  shape: Vec<usize>,
+) -> PyResult<Bound<'py, PyArrayDyn<T>>> {
+  PyArray1::from_vec_bound(py, values).reshape(shape)
+}
+
 impl TryFrom<SIRange> for ::spdcalc::FrequencySpace {
   type Error = PyErr;
   fn try_from(si: SIRange) -> Result<Self, Self::Error> {
     match si {
       SIRange::FrequencySpace(fs) => Ok(fs.0),
-      SIRange::FrequencyArray(_) => Err(PyValueError::new_err(
+      SIRange::FrequencyArray(_) => Err(UnitError::new_err(
         "Cannot convert FrequencyArray to FrequencySpace",
       )),
       SIRange::Wavelength(ws) => Ok(ws.0.as_frequency_space()),
-      SIRange::WavelengthArray(_) => Err(PyValueError::new_err(
+      SIRange::WavelengthArray(_) => Err(UnitError::new_err(
         "Cannot convert WavelengthArray to FrequencySpace",
       )),
       SIRange::SumDiffFrequency(sdfs) => Ok(sdfs.0.as_frequency_space()),
@@ -117,8 +238,11 @@ impl Iterator for SIIterator {
   }
 }
 
+/// Constructor arguments of the 2D spaces: `(xsteps, ysteps)`
+type StepsArgs = ((f64, f64, usize), (f64, f64, usize));
+
 /// Represents a range of signal-idler frequencies
-#[pyclass]
+#[pyclass(module = "spdcalc")]
 #[derive(Debug, Clone)]
 pub(crate) struct FrequencySpace(pub(crate) ::spdcalc::FrequencySpace);
 
@@ -238,6 +362,31 @@ impl FrequencySpace {
       .collect()
   }
 
+  // pickle support. Reconstructed from the constructor arguments.
+  fn __reduce__<'py>(slf: &Bound<'py, Self>) -> (Bound<'py, PyType>, StepsArgs) {
+    (slf.get_type(), slf.borrow().steps_args())
+  }
+
+  fn __eq__(&self, other: &Self) -> bool {
+    self.steps_args() == other.steps_args()
+  }
+
+  fn __hash__(&self) -> u64 {
+    let (x, y) = self.steps_args();
+    hash_of(&(
+      (float_key(x.0), float_key(x.1), x.2),
+      (float_key(y.0), float_key(y.1), y.2),
+    ))
+  }
+
+  fn __copy__(&self) -> Self {
+    self.clone()
+  }
+
+  fn __deepcopy__(&self, _memo: &Bound<'_, PyDict>) -> Self {
+    self.clone()
+  }
+
   /// String representation of FrequencySpace
   ///
   /// Returns
@@ -258,6 +407,24 @@ impl FrequencySpace {
   }
 }
 
+impl FrequencySpace {
+  /// The constructor arguments `(xsteps, ysteps)` of this space
+  fn steps_args(&self) -> StepsArgs {
+    let steps = self.0.as_steps();
+    let x = (
+      *(steps.0 .0 / RAD / HZ),
+      *(steps.0 .1 / RAD / HZ),
+      steps.0 .2,
+    );
+    let y = (
+      *(steps.1 .0 / RAD / HZ),
+      *(steps.1 .1 / RAD / HZ),
+      steps.1 .2,
+    );
+    (x, y)
+  }
+}
+
 impl From<::spdcalc::FrequencySpace> for FrequencySpace {
   fn from(fs: ::spdcalc::FrequencySpace) -> Self {
     Self(fs)
@@ -265,7 +432,7 @@ impl From<::spdcalc::FrequencySpace> for FrequencySpace {
 }
 
 /// Represents a range of signal-idler wavelengths
-#[pyclass]
+#[pyclass(module = "spdcalc")]
 #[derive(Debug, Clone)]
 pub(crate) struct WavelengthSpace(pub(crate) ::spdcalc::WavelengthSpace);
 
@@ -385,6 +552,31 @@ impl WavelengthSpace {
       .collect()
   }
 
+  // pickle support. Reconstructed from the constructor arguments.
+  fn __reduce__<'py>(slf: &Bound<'py, Self>) -> (Bound<'py, PyType>, StepsArgs) {
+    (slf.get_type(), slf.borrow().steps_args())
+  }
+
+  fn __eq__(&self, other: &Self) -> bool {
+    self.steps_args() == other.steps_args()
+  }
+
+  fn __hash__(&self) -> u64 {
+    let (x, y) = self.steps_args();
+    hash_of(&(
+      (float_key(x.0), float_key(x.1), x.2),
+      (float_key(y.0), float_key(y.1), y.2),
+    ))
+  }
+
+  fn __copy__(&self) -> Self {
+    self.clone()
+  }
+
+  fn __deepcopy__(&self, _memo: &Bound<'_, PyDict>) -> Self {
+    self.clone()
+  }
+
   /// String representation of WavelengthSpace
   ///
   /// Returns
@@ -405,6 +597,16 @@ impl WavelengthSpace {
   }
 }
 
+impl WavelengthSpace {
+  /// The constructor arguments `(xsteps, ysteps)` of this space
+  fn steps_args(&self) -> StepsArgs {
+    let steps = self.0.as_steps();
+    let x = (*(steps.0 .0 / M), *(steps.0 .1 / M), steps.0 .2);
+    let y = (*(steps.1 .0 / M), *(steps.1 .1 / M), steps.1 .2);
+    (x, y)
+  }
+}
+
 impl From<::spdcalc::WavelengthSpace> for WavelengthSpace {
   fn from(ws: ::spdcalc::WavelengthSpace) -> Self {
     Self(ws)
@@ -414,7 +616,7 @@ impl From<::spdcalc::WavelengthSpace> for WavelengthSpace {
 /// Represents a range of signal-idler frequencies such that
 /// one axis is the sum of two frequencies (divided by 2) and the other axis
 /// is the difference of two frequencies (divided by 2)
-#[pyclass]
+#[pyclass(module = "spdcalc")]
 #[derive(Debug, Clone)]
 pub(crate) struct SumDiffFrequencySpace(pub(crate) ::spdcalc::SumDiffFrequencySpace);
 
@@ -534,6 +736,31 @@ impl SumDiffFrequencySpace {
       .collect()
   }
 
+  // pickle support. Reconstructed from the constructor arguments.
+  fn __reduce__<'py>(slf: &Bound<'py, Self>) -> (Bound<'py, PyType>, StepsArgs) {
+    (slf.get_type(), slf.borrow().steps_args())
+  }
+
+  fn __eq__(&self, other: &Self) -> bool {
+    self.steps_args() == other.steps_args()
+  }
+
+  fn __hash__(&self) -> u64 {
+    let (x, y) = self.steps_args();
+    hash_of(&(
+      (float_key(x.0), float_key(x.1), x.2),
+      (float_key(y.0), float_key(y.1), y.2),
+    ))
+  }
+
+  fn __copy__(&self) -> Self {
+    self.clone()
+  }
+
+  fn __deepcopy__(&self, _memo: &Bound<'_, PyDict>) -> Self {
+    self.clone()
+  }
+
   /// String representation of SumDiffFrequencySpace
   ///
   /// Returns
@@ -554,6 +781,24 @@ impl SumDiffFrequencySpace {
   }
 }
 
+impl SumDiffFrequencySpace {
+  /// The constructor arguments `(xsteps, ysteps)` of this space
+  fn steps_args(&self) -> StepsArgs {
+    let steps = self.0.as_steps();
+    let x = (
+      *(steps.0 .0 / RAD / HZ),
+      *(steps.0 .1 / RAD / HZ),
+      steps.0 .2,
+    );
+    let y = (
+      *(steps.1 .0 / RAD / HZ),
+      *(steps.1 .1 / RAD / HZ),
+      steps.1 .2,
+    );
+    (x, y)
+  }
+}
+
 impl From<::spdcalc::SumDiffFrequencySpace> for SumDiffFrequencySpace {
   fn from(sdfs: ::spdcalc::SumDiffFrequencySpace) -> Self {
     Self(sdfs)
@@ -561,7 +806,7 @@ impl From<::spdcalc::SumDiffFrequencySpace> for SumDiffFrequencySpace {
 }
 
 /// Represents an array of signal-idler frequencies
-#[pyclass]
+#[pyclass(module = "spdcalc")]
 #[derive(Debug, Clone)]
 pub(crate) struct FrequencyArray(pub(crate) ::spdcalc::SignalIdlerFrequencyArray);
 
@@ -571,18 +816,40 @@ impl FrequencyArray {
   ///
   /// Parameters
   /// ----------
-  /// frequencies : list of float
-  ///     List of frequencies in Hz
+  /// frequencies : list of float or numpy.ndarray
+  ///     Frequencies in Hz, either as a flat list of interleaved signal and idler
+  ///     values, or as an array of shape (N, 2) of signal-idler pairs
   ///
   /// Returns
   /// -------
   /// :class:`FrequencyArray`
   ///     New FrequencyArray object
   #[new]
-  pub fn new(frequencies: Vec<f64>) -> Self {
-    Self(::spdcalc::SignalIdlerFrequencyArray(
-      frequencies.into_iter().map(|f| f * RAD * HZ).collect(),
-    ))
+  pub fn new(frequencies: FloatArray<'_>) -> PyResult<Self> {
+    Ok(Self(::spdcalc::SignalIdlerFrequencyArray(
+      frequencies.map_pairs(|f| f * RAD * HZ)?,
+    )))
+  }
+
+  // pickle support. Reconstructed from the flat list of values.
+  fn __reduce__<'py>(slf: &Bound<'py, Self>) -> (Bound<'py, PyType>, (Vec<f64>,)) {
+    (slf.get_type(), (slf.borrow().values(),))
+  }
+
+  fn __eq__(&self, other: &Self) -> bool {
+    self.values() == other.values()
+  }
+
+  fn __hash__(&self) -> u64 {
+    hash_of(&self.values().into_iter().map(float_key).collect::<Vec<_>>())
+  }
+
+  fn __copy__(&self) -> Self {
+    self.clone()
+  }
+
+  fn __deepcopy__(&self, _memo: &Bound<'_, PyDict>) -> Self {
+    self.clone()
   }
 
   /// String representation of FrequencyArray
@@ -627,6 +894,13 @@ impl FrequencyArray {
   }
 }
 
+impl FrequencyArray {
+  /// The flat list of interleaved signal-idler values this array was constructed from
+  fn values(&self) -> Vec<f64> {
+    self.0 .0.iter().map(|&v| *(v / RAD / HZ)).collect()
+  }
+}
+
 impl From<::spdcalc::SignalIdlerFrequencyArray> for FrequencyArray {
   fn from(fa: ::spdcalc::SignalIdlerFrequencyArray) -> Self {
     Self(fa)
@@ -634,7 +908,7 @@ impl From<::spdcalc::SignalIdlerFrequencyArray> for FrequencyArray {
 }
 
 /// Represents an array of signal-idler wavelengths
-#[pyclass]
+#[pyclass(module = "spdcalc")]
 #[derive(Debug, Clone)]
 pub(crate) struct WavelengthArray(pub(crate) ::spdcalc::SignalIdlerWavelengthArray);
 
@@ -644,18 +918,19 @@ impl WavelengthArray {
   ///
   /// Parameters
   /// ----------
-  /// wavelengths : list of float
-  ///     List of wavelengths in meters
+  /// wavelengths : list of float or numpy.ndarray
+  ///     Wavelengths in meters, either as a flat list of interleaved signal and idler
+  ///     values, or as an array of shape (N, 2) of signal-idler pairs
   ///
   /// Returns
   /// -------
   /// WavelengthArray
   ///     New WavelengthArray object
   #[new]
-  pub fn new(wavelengths: Vec<f64>) -> Self {
-    Self(::spdcalc::SignalIdlerWavelengthArray(
-      wavelengths.into_iter().map(|w| w * M).collect(),
-    ))
+  pub fn new(wavelengths: FloatArray<'_>) -> PyResult<Self> {
+    Ok(Self(::spdcalc::SignalIdlerWavelengthArray(
+      wavelengths.map_pairs(|w| w * M)?,
+    )))
   }
 
   /// Get a list of x-axis values
@@ -683,13 +958,79 @@ impl WavelengthArray {
       .collect()
   }
 
+  // pickle support. Reconstructed from the flat list of values.
+  fn __reduce__<'py>(slf: &Bound<'py, Self>) -> (Bound<'py, PyType>, (Vec<f64>,)) {
+    (slf.get_type(), (slf.borrow().values(),))
+  }
+
+  fn __eq__(&self, other: &Self) -> bool {
+    self.values() == other.values()
+  }
+
+  fn __hash__(&self) -> u64 {
+    hash_of(&self.values().into_iter().map(float_key).collect::<Vec<_>>())
+  }
+
+  fn __copy__(&self) -> Self {
+    self.clone()
+  }
+
+  fn __deepcopy__(&self, _memo: &Bound<'_, PyDict>) -> Self {
+    self.clone()
+  }
+
   pub fn __repr__(&self) -> String {
     format!("{:?}", self.0)
   }
 }
 
+impl WavelengthArray {
+  /// The flat list of interleaved signal-idler values this array was constructed from
+  fn values(&self) -> Vec<f64> {
+    self.0 .0.iter().map(|&v| *(v / M)).collect()
+  }
+}
+
 impl From<::spdcalc::SignalIdlerWavelengthArray> for WavelengthArray {
   fn from(wa: ::spdcalc::SignalIdlerWavelengthArray) -> Self {
     Self(wa)
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn ranges_are_reconstructed_from_axes() {
+    let ranges = [
+      SIRange::FrequencySpace(FrequencySpace::new((1e15, 1.2e15, 5), (1.1e15, 1.3e15, 3))),
+      SIRange::Wavelength(WavelengthSpace::new(
+        (1.5e-6, 1.6e-6, 4),
+        (1.5e-6, 1.5e-6, 1),
+      )),
+      SIRange::SumDiffFrequency(SumDiffFrequencySpace::new(
+        (1e15, 1.2e15, 2),
+        (-1e12, 1e12, 7),
+      )),
+      SIRange::FrequencyArray(
+        FrequencyArray::new(FloatArray::List(vec![1e15, 1.1e15, 1.2e15, 1.3e15])).unwrap(),
+      ),
+      SIRange::WavelengthArray(
+        WavelengthArray::new(FloatArray::List(vec![1.5e-6, 1.6e-6])).unwrap(),
+      ),
+    ];
+    for range in ranges {
+      let (x, y) = range.axes();
+      let restored = SIRange::from_axes(range.kind(), &x, &y).unwrap();
+      assert_eq!(restored.kind(), range.kind());
+      assert_eq!(restored.shape(), range.shape());
+      let (rx, ry) = restored.axes();
+      for (a, b) in rx.iter().chain(&ry).zip(x.iter().chain(&y)) {
+        assert!((a - b).abs() <= 1e-12 * b.abs(), "{} != {}", a, b);
+      }
+    }
+    assert!(SIRange::from_axes("time", &[1.], &[1.]).is_err());
+    assert!(SIRange::from_axes("frequency_array", &[1., 2.], &[1.]).is_err());
+  }
+}
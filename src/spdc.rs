@@ -1,11 +1,15 @@
 use std::collections::HashMap;
 
 use crate::*;
+use ::spdcalc::beam::BeamWaist;
 use ::spdcalc::dim::{f64prefixes::*, ucum::*};
-use ::spdcalc::SPDCConfig;
+use ::spdcalc::{AutoCalcParam, PeriodicPolingConfig, SPDCConfig};
+use numpy::PyArray1;
 use pyo3::exceptions::PyValueError;
+use pyo3::types::{PyDict, PyTuple, PyType};
+use rayon::prelude::*;
 use spdcalc::utils::{from_celsius_to_kelvin, from_kelvin_to_celsius};
-use spdcalc::{Apodization, PMType, PeriodicPoling, Time};
+use spdcalc::{Apodization, IntoSignalIdlerIterator, PMType, PeriodicPoling, Time};
 
 pub(crate) type Visibility = HashMap<String, f64>;
 
@@ -18,47 +22,256 @@ pub(crate) type Visibility = HashMap<String, f64>;
 /// instance with default values. These can then be modified as needed.
 ///
 /// 2. By using the `SPDC.from_yaml(yaml: str)` or `SPDC.from_json(json: str)`
-#[pyclass]
+///
+/// Long running calculations (counts, efficiencies, HOM) release the GIL while they
+/// run, so independent setups can be computed concurrently from multiple python threads.
+#[pyclass(module = "spdcalc")]
 #[derive(Debug, Clone)]
-pub(crate) struct SPDC(pub(crate) ::spdcalc::SPDC);
+pub(crate) struct SPDC(
+  pub(crate) ::spdcalc::SPDC,
+  pub(crate) PumpSpectrum,
+  // the config fields that were auto, see `auto_fields`
+  pub(crate) config::AutoFields,
+);
+
+/// The pickled state of an SPDC object: its exact JSON config and the paths of its auto fields
+type State = (String, Vec<&'static str>);
+
+/// A pickled state, as read back
+#[derive(FromPyObject)]
+enum PickleState {
+  WithAuto(String, Vec<String>),
+  #[pyo3(transparent)]
+  Config(String),
+}
 
 #[pymethods]
 impl SPDC {
   // allows for nice print statements in python
   fn __repr__(&self) -> pyo3::PyResult<String> {
-    Ok(format!("{}", self.to_yaml()?))
+    Ok(format!("{}", self.to_yaml(false)?))
+  }
+
+  /// Create a new SPDC object with default values
+  ///
+  /// Equivalent to `SPDC.default()`
+  #[new]
+  pub fn new() -> Self {
+    Self::default()
   }
 
   /// Create a new SPDC object with default values
   #[staticmethod]
   pub fn default() -> Self {
-    SPDC(spdcalc::SPDC::default())
+    SPDC(
+      spdcalc::SPDC::default(),
+      PumpSpectrum::default(),
+      config::AutoFields::default(),
+    )
+  }
+
+  // pickle support. The state is the full precision JSON config and the
+  // paths of the auto fields.
+  fn __getstate__(&self) -> Result<State, PySpdcError> {
+    self.state()
+  }
+
+  fn __setstate__(&mut self, state: PickleState) -> PyResult<()> {
+    *self = match state {
+      PickleState::WithAuto(json, auto) => Self::from_state(&json, &auto)?,
+      // states from before auto fields were remembered
+      PickleState::Config(json) => Self::from_json(&json)?,
+    };
+    Ok(())
+  }
+
+  fn __reduce__<'py>(
+    slf: &Bound<'py, Self>,
+  ) -> PyResult<(Bound<'py, PyType>, Bound<'py, PyTuple>, State)> {
+    let args = PyTuple::empty_bound(slf.py());
+    Ok((slf.get_type(), args, slf.borrow().__getstate__()?))
+  }
+
+  fn __copy__(&self) -> Self {
+    self.clone()
+  }
+
+  fn __deepcopy__(&self, _memo: &Bound<'_, PyDict>) -> Self {
+    self.clone()
+  }
+
+  // Equality uses the full precision config, so setups are equal exactly when
+  // they describe the same setup. Use `is_close` for a tolerance. Setups are
+  // mutable, so they are not hashable; use `key` for dicts and sets.
+  fn __eq__(&self, other: &Self) -> Result<bool, PySpdcError> {
+    Ok(self.canonical_config()? == other.canonical_config()?)
+  }
+
+  /// A hashable key of this setup, for use in dicts and sets
+  ///
+  /// This is the full precision config as JSON, so setups that compare equal
+  /// have the same key. Unlike the setup, the key does not change when the
+  /// setup is changed afterwards.
+  ///
+  /// Returns
+  /// -------
+  /// `str`
+  pub fn key(&self) -> Result<String, PySpdcError> {
+    Ok(self.canonical_config()?.to_string())
+  }
+
+  /// Check whether two setups are equal within a tolerance
+  ///
+  /// Every numeric value of the full precision configs is compared as
+  /// `abs(a - b) <= max(rtol * max(abs(a), abs(b)), atol)`. All other values
+  /// must be equal.
+  ///
+  /// Parameters
+  /// ----------
+  /// other : SPDC
+  ///     The setup to compare against
+  /// rtol : float, optional
+  ///     The relative tolerance, by default 1e-9
+  /// atol : float, optional
+  ///     The absolute tolerance, by default 0
+  ///
+  /// Returns
+  /// -------
+  /// `bool`
+  #[pyo3(signature = (other, rtol = 1e-9, atol = 0.))]
+  pub fn is_close(&self, other: &Self, rtol: f64, atol: f64) -> Result<bool, PySpdcError> {
+    let a = serde_json::to_value(self.config_value(true))?;
+    let b = serde_json::to_value(other.config_value(true))?;
+    Ok(json_is_close(&a, &b, rtol, atol))
   }
 
   /// Create a new SPDC object from a YAML string
+  ///
+  /// Raises :class:`ConfigError` with the ``path`` (and ``line`` and ``column``, where
+  /// known) of the offending value if the YAML is not a valid config.
   #[staticmethod]
-  pub fn from_yaml(yaml: &str) -> Result<Self, PyErr> {
-    let spdc: ::spdcalc::SPDC =
-      serde_yaml::from_str(&yaml).map_err(|e| PyValueError::new_err(e.to_string()))?;
-    Ok(Self(spdc))
+  pub fn from_yaml(yaml: &str) -> Result<Self, PyConfigError> {
+    config::parse_config(yaml, config::Format::Yaml)
   }
 
   /// Create a new SPDC object from a JSON string
+  ///
+  /// Raises :class:`ConfigError` with the ``path`` (and ``line`` and ``column``, where
+  /// known) of the offending value if the JSON is not a valid config.
   #[staticmethod]
-  pub fn from_json(json: &str) -> Result<Self, PyErr> {
-    let spdc: ::spdcalc::SPDC =
-      serde_json::from_str(&json).map_err(|e| PyValueError::new_err(e.to_string()))?;
-    Ok(Self(spdc))
+  pub fn from_json(json: &str) -> Result<Self, PyConfigError> {
+    config::parse_config(json, config::Format::Json)
+  }
+
+  /// Create a new SPDC object from a TOML string
+  ///
+  /// The config is the same as for YAML, e.g. ``idler = "auto"`` and
+  /// ``theta_deg = "auto"``. TOML has no null, so optional values are left out.
+  ///
+  /// Raises :class:`ConfigError` with the ``path`` (and ``line`` and ``column``, where
+  /// known) of the offending value if the TOML is not a valid config.
+  #[staticmethod]
+  pub fn from_toml(toml: &str) -> Result<Self, PyConfigError> {
+    config::parse_config(toml, config::Format::Toml)
+  }
+
+  /// Create a new SPDC object from a config file
+  ///
+  /// The format is chosen by the extension of the file: ``.yaml`` or ``.yml``,
+  /// ``.json`` or ``.toml``.
+  ///
+  /// Parameters
+  /// ----------
+  /// path : str or os.PathLike
+  ///     The config file
+  ///
+  /// Returns
+  /// -------
+  /// :class:`SPDC`
+  ///     The SPDC object
+  #[staticmethod]
+  pub fn from_file(path: std::path::PathBuf) -> PyResult<Self> {
+    let format = config::Format::from_path(&path).ok_or_else(|| {
+      PyValueError::new_err(format!(
+        "Can not tell the config format of '{}'. Expected a .yaml, .yml, .json or .toml file",
+        path.display()
+      ))
+    })?;
+    let text = std::fs::read_to_string(&path)?;
+    Ok(config::parse_config(&text, format)?)
+  }
+
+  /// Check a config for problems without creating an SPDC object
+  ///
+  /// Unlike :meth:`from_yaml` and :meth:`from_json`, which stop at the first
+  /// problem, this collects all of them. Errors are configs that can not be used,
+  /// like a value of the wrong type, a missing field, a non-positive length or waist,
+  /// or a signal or idler wavelength shorter than the pump's, and so are setups that
+  /// can not be built, like an auto theta with periodic poling. Physics problems of
+  /// a setup that can be built, like a poling period that does not phasematch, are
+  /// warnings. Physics is only checked once there are no errors.
+  ///
+  /// Parameters
+  /// ----------
+  /// text : str
+  ///     The config
+  /// format : str, optional
+  ///     The format of the config, ``"yaml"`` (default), ``"json"`` or ``"toml"``
+  ///
+  /// Returns
+  /// -------
+  /// `list` of `dict`
+  ///     The problems found, empty for a valid config. Each has the keys ``severity``
+  ///     (``"error"`` or ``"warning"``), ``path`` (the dotted config path of the
+  ///     offending value, e.g. ``"pump.waist_um"``, or None), ``message``, and
+  ///     ``line`` and ``column`` (of syntax errors, otherwise None).
+  #[staticmethod]
+  #[pyo3(signature = (text, format = config::Format::Yaml))]
+  pub fn validate_config(py: Python<'_>, text: &str, format: config::Format) -> Vec<PyObject> {
+    validate::validate_config(text, format)
+      .into_iter()
+      .map(|diagnostic| diagnostic.into_py(py))
+      .collect()
   }
 
   /// Convert the SPDC object to a YAML string
-  pub fn to_yaml(&self) -> Result<String, PySpdcError> {
-    Ok(serde_yaml::to_string(&SPDCConfig::from(self.0.clone())).unwrap())
+  ///
+  /// Parameters
+  /// ----------
+  /// preserve_auto : bool, optional
+  ///     Write ``auto`` for the fields that are calculated automatically (see
+  ///     :attr:`auto_fields`) instead of their current values. Defaults to False.
+  #[pyo3(signature = (preserve_auto = false))]
+  pub fn to_yaml(&self, preserve_auto: bool) -> Result<String, PySpdcError> {
+    Ok(serde_yaml::to_string(&self.written_config(preserve_auto)).unwrap())
   }
 
   /// Convert the SPDC object to a JSON string
-  pub fn to_json(&self) -> Result<String, PySpdcError> {
-    Ok(serde_json::to_string(&SPDCConfig::from(self.0.clone())).unwrap())
+  ///
+  /// Parameters
+  /// ----------
+  /// preserve_auto : bool, optional
+  ///     Write ``"auto"`` for the fields that are calculated automatically (see
+  ///     :attr:`auto_fields`) instead of their current values. Defaults to False.
+  #[pyo3(signature = (preserve_auto = false))]
+  pub fn to_json(&self, preserve_auto: bool) -> Result<String, PySpdcError> {
+    Ok(serde_json::to_string(&self.written_config(preserve_auto)).unwrap())
+  }
+
+  /// Convert the SPDC object to a TOML string
+  ///
+  /// Null values (like an unset ``theta_external_deg``) are left out, as TOML
+  /// can not represent them.
+  ///
+  /// Parameters
+  /// ----------
+  /// preserve_auto : bool, optional
+  ///     Write ``"auto"`` for the fields that are calculated automatically (see
+  ///     :attr:`auto_fields`) instead of their current values. Defaults to False.
+  #[pyo3(signature = (preserve_auto = false))]
+  pub fn to_toml(&self, preserve_auto: bool) -> Result<String, PySpdcError> {
+    let value = config::without_nulls(self.written_config(preserve_auto));
+    toml::to_string(&value).map_err(|e| PySpdcError(::spdcalc::SPDCError(e.to_string())))
   }
 
   // Getters and setters
@@ -114,6 +327,7 @@ impl SPDC {
 
   #[setter]
   pub fn set_crystal_theta_deg(&mut self, value: f64) {
+    self.2.crystal_theta = false;
     self.0.crystal_setup.theta = value * DEG;
   }
 
@@ -175,6 +389,8 @@ impl SPDC {
   }
 
   /// The pump waist in nanometers
+  ///
+  /// For an elliptical waist this is the x waist. Setting it makes the waist circular.
   #[getter]
   pub fn pump_waist_nm(&self) -> f64 {
     *(self.0.pump.waist().x / NANO / M)
@@ -185,6 +401,23 @@ impl SPDC {
     self.0.pump.set_waist(value * NANO * M);
   }
 
+  /// The pump waist in nanometers along x and y, as a tuple
+  ///
+  /// Elliptical waists are written to configs as ``waist_um: [x, y]``
+  #[getter]
+  pub fn pump_waist_xy_nm(&self) -> (f64, f64) {
+    let waist = self.0.pump.waist();
+    (*(waist.x / NANO / M), *(waist.y / NANO / M))
+  }
+
+  #[setter]
+  pub fn set_pump_waist_xy_nm(&mut self, value: (f64, f64)) {
+    self.0.pump.set_waist(BeamWaist {
+      x: value.0 * NANO * M,
+      y: value.1 * NANO * M,
+    });
+  }
+
   /// The pump spectral bandwidth in nanometers
   #[getter]
   pub fn pump_bandwidth_nm(&self) -> f64 {
@@ -199,17 +432,18 @@ impl SPDC {
   /// The pump average power in milliwatts
   #[getter]
   pub fn pump_average_power_mw(&self) -> f64 {
-    *(self.0.pump_average_power / MEGA / W)
+    *(self.0.pump_average_power / MILLIW)
   }
 
   #[setter]
   pub fn set_pump_average_power_mw(&mut self, value: f64) {
-    self.0.pump_average_power = value * MEGA * W;
+    self.0.pump_average_power = value * MILLIW;
   }
 
   /// The pump spectrum threshold
   ///
-  /// Values below this threshold are considered to be zero
+  /// Values below this threshold are considered to be zero. For sampled pump
+  /// spectra it is relative to the largest sampled amplitude.
   #[getter]
   pub fn pump_spectrum_threshold(&self) -> f64 {
     self.0.pump_spectrum_threshold
@@ -220,6 +454,28 @@ impl SPDC {
     self.0.pump_spectrum_threshold = value;
   }
 
+  /// The spectral shape of the pump
+  ///
+  /// Either the name of a shape whose width is the pump bandwidth:
+  /// ``"gaussian"`` (the default), ``"sech2"`` (a sech² intensity, as from
+  /// a mode-locked laser) or ``"rect"`` (a flat top), or a sampled spectrum
+  /// given as a dict with the keys ``wavelengths_nm`` (vacuum wavelengths) and
+  /// ``amplitudes`` (spectral amplitudes, linearly interpolated and zero outside
+  /// the samples). In configs it is written as ``pump.spectrum``.
+  ///
+  /// The spectrum is normalized to carry the pump average power, so only its
+  /// shape matters. Optimum ranges are still estimated from the pump bandwidth,
+  /// and two-source HOM calculations need a gaussian pump.
+  #[getter]
+  pub fn pump_spectrum(&self, py: Python<'_>) -> PyObject {
+    self.1.clone().into_py(py)
+  }
+
+  #[setter]
+  pub fn set_pump_spectrum(&mut self, value: PumpSpectrum) {
+    self.1 = value;
+  }
+
   // signal
 
   /// The signal wavelength in nanometers
@@ -280,7 +536,9 @@ impl SPDC {
       .set_theta_external(value * DEG, &self.0.crystal_setup);
   }
 
-  /// The signal waist in nanometers (x, y)
+  /// The signal waist in micrometers
+  ///
+  /// For an elliptical waist this is the x waist. Setting it makes the waist circular.
   #[getter]
   pub fn signal_waist_um(&self) -> f64 {
     *(self.0.signal.waist().x / MICRO / M)
@@ -291,6 +549,24 @@ impl SPDC {
     self.0.signal.set_waist(value * MICRO * M);
   }
 
+  /// The signal waist in micrometers along x and y, as a tuple
+  ///
+  /// The signal mode enters the calculations through the product of its waists.
+  /// Elliptical waists are written to configs as ``waist_um: [x, y]``
+  #[getter]
+  pub fn signal_waist_xy_um(&self) -> (f64, f64) {
+    let waist = self.0.signal.waist();
+    (*(waist.x / MICRO / M), *(waist.y / MICRO / M))
+  }
+
+  #[setter]
+  pub fn set_signal_waist_xy_um(&mut self, value: (f64, f64)) {
+    self.0.signal.set_waist(BeamWaist {
+      x: value.0 * MICRO * M,
+      y: value.1 * MICRO * M,
+    });
+  }
+
   /// The signal waist position in micrometers
   #[getter]
   pub fn signal_waist_position_um(&self) -> f64 {
@@ -299,6 +575,7 @@ impl SPDC {
 
   #[setter]
   pub fn set_signal_waist_position_um(&mut self, value: f64) {
+    self.2.signal_waist_position = false;
     self.0.signal_waist_position = value * MICRO * M;
   }
 
@@ -312,6 +589,7 @@ impl SPDC {
 
   #[setter]
   pub fn set_idler_wavelength_nm(&mut self, value: f64) {
+    self.2.set_explicit_idler();
     self.0.idler.set_vacuum_wavelength(value * NANO * M);
   }
 
@@ -323,6 +601,7 @@ impl SPDC {
 
   #[setter]
   pub fn set_idler_frequency_rad_per_s(&mut self, value: f64) {
+    self.2.set_explicit_idler();
     self.0.idler.set_frequency(value * RAD / S);
   }
 
@@ -334,6 +613,7 @@ impl SPDC {
 
   #[setter]
   pub fn set_idler_phi_deg(&mut self, value: f64) {
+    self.2.set_explicit_idler();
     self.0.idler.set_phi(value * DEG);
   }
 
@@ -345,6 +625,7 @@ impl SPDC {
 
   #[setter]
   pub fn set_idler_theta_deg(&mut self, value: f64) {
+    self.2.set_explicit_idler();
     self.0.idler.set_theta_internal(value * DEG);
   }
 
@@ -356,13 +637,16 @@ impl SPDC {
 
   #[setter]
   pub fn set_idler_theta_external_deg(&mut self, value: f64) {
+    self.2.set_explicit_idler();
     self
       .0
       .idler
       .set_theta_external(value * DEG, &self.0.crystal_setup);
   }
 
-  /// The idler waist in nanometers
+  /// The idler waist in micrometers
+  ///
+  /// For an elliptical waist this is the x waist. Setting it makes the waist circular.
   #[getter]
   pub fn idler_waist_um(&self) -> f64 {
     *(self.0.idler.waist().x / MICRO / M)
@@ -370,9 +654,29 @@ impl SPDC {
 
   #[setter]
   pub fn set_idler_waist_um(&mut self, value: f64) {
+    self.2.set_explicit_idler();
     self.0.idler.set_waist(value * MICRO * M);
   }
 
+  /// The idler waist in micrometers along x and y, as a tuple
+  ///
+  /// The idler mode enters the calculations through the product of its waists.
+  /// Elliptical waists are written to configs as ``waist_um: [x, y]``
+  #[getter]
+  pub fn idler_waist_xy_um(&self) -> (f64, f64) {
+    let waist = self.0.idler.waist();
+    (*(waist.x / MICRO / M), *(waist.y / MICRO / M))
+  }
+
+  #[setter]
+  pub fn set_idler_waist_xy_um(&mut self, value: (f64, f64)) {
+    self.2.set_explicit_idler();
+    self.0.idler.set_waist(BeamWaist {
+      x: value.0 * MICRO * M,
+      y: value.1 * MICRO * M,
+    });
+  }
+
   /// The idler waist position in micrometers
   #[getter]
   pub fn idler_waist_position_um(&self) -> f64 {
@@ -381,33 +685,48 @@ impl SPDC {
 
   #[setter]
   pub fn set_idler_waist_position_um(&mut self, value: f64) {
+    self.2.set_explicit_idler();
+    self.2.idler_waist_position = false;
     self.0.idler_waist_position = value * MICRO * M;
   }
 
   // periodic poling
 
   /// The poling period in micrometers
+  ///
+  /// A negative period means the sign of the poling is reversed from the one
+  /// that phasematches the setup. Configs are written the same way, so the sign
+  /// survives `to_yaml` and friends.
   #[getter]
   pub fn poling_period_um(&self) -> Option<f64> {
     match self.0.pp {
       PeriodicPoling::Off => None,
-      PeriodicPoling::On { period, .. } => Some(*(period / MICRO / M)),
+      PeriodicPoling::On { period, .. } => {
+        let period = *(period / MICRO / M);
+        Some(if self.poling_is_reversed() {
+          -period
+        } else {
+          period
+        })
+      }
     }
   }
 
   #[setter]
   pub fn set_poling_period_um(&mut self, value: Option<f64>) {
+    self.2.poling_period = false;
     if let None = value {
       self.0.pp = PeriodicPoling::Off;
       return;
     }
     let value = value.unwrap();
+    // a negative value reverses the phasematching sign, as in configs
+    let period = self.phasematching_poling_sign() * value.abs() * MICRO * M;
+    let period = if value < 0. { -period } else { period };
     match &self.0.pp {
-      PeriodicPoling::Off => {
-        self.0.pp = PeriodicPoling::new(value * MICRO * M, spdcalc::Apodization::Off)
-      }
+      PeriodicPoling::Off => self.0.pp = PeriodicPoling::new(period, spdcalc::Apodization::Off),
       PeriodicPoling::On { .. } => {
-        self.0.pp = self.0.pp.clone().with_period(value * MICRO * M);
+        self.0.pp = self.0.pp.clone().with_period(period);
       }
     };
   }
@@ -511,9 +830,129 @@ impl SPDC {
   /// Swap the signal and idler
   pub fn with_swapped_signal_idler(mut slf: PyRefMut<'_, Self>) -> PyRefMut<'_, Self> {
     slf.0 = slf.0.clone().with_swapped_signal_idler().into();
+    // the new idler is the old signal, which is never derived
+    let auto = slf.2;
+    slf.2.idler = false;
+    slf.2.signal_waist_position = auto.idler_waist_position;
+    slf.2.idler_waist_position = auto.signal_waist_position;
     slf
   }
 
+  /// The config paths of the fields that are calculated automatically
+  ///
+  /// These are the fields that were ``auto`` in the config this setup was created
+  /// from, out of ``crystal.theta_deg``, ``periodic_poling.poling_period_um``,
+  /// ``signal.waist_position_um``, ``idler`` and ``idler.waist_position_um``.
+  /// Setting one of these values (or any idler value, for ``idler``) makes it explicit.
+  /// Their values are not updated when other values change, use :meth:`resolve_auto`
+  /// for that.
+  ///
+  /// Returns
+  /// -------
+  /// `list` of `str`
+  #[getter]
+  pub fn auto_fields(&self) -> Vec<&'static str> {
+    self.2.paths()
+  }
+
+  /// Compare the config of this setup with another
+  ///
+  /// The full precision configs are compared value by value, with numbers
+  /// compared as in :meth:`is_close`.
+  ///
+  /// Parameters
+  /// ----------
+  /// other : SPDC
+  ///     The setup to compare against
+  /// rtol : float, optional
+  ///     The relative tolerance, by default 1e-9
+  /// atol : float, optional
+  ///     The absolute tolerance, by default 0
+  ///
+  /// Returns
+  /// -------
+  /// `list` of `dict`
+  ///     The values that differ, ordered by path. Each has the keys ``path`` (the
+  ///     dotted config path, e.g. ``"pump.waist_um"``), ``old`` (the value of this
+  ///     setup), ``new`` (the value of ``other``) and ``unit`` (e.g. ``"um"``, or None).
+  ///     A section that only one setup has, like ``periodic_poling``, is a single
+  ///     difference with None for the other value.
+  #[pyo3(signature = (other, rtol = 1e-9, atol = 0.))]
+  pub fn diff(&self, py: Python<'_>, other: &Self, rtol: f64, atol: f64) -> PyResult<PyObject> {
+    let old = serde_json::to_value(self.config_value(true)).map_err(PySpdcError::from)?;
+    let new = serde_json::to_value(other.config_value(true)).map_err(PySpdcError::from)?;
+    let changes: Vec<serde_json::Value> = diff::diff(&old, &new, rtol, atol)
+      .iter()
+      .map(diff::Change::to_json)
+      .collect();
+    let json = py.import_bound("json")?;
+    Ok(
+      json
+        .call_method1("loads", (serde_json::Value::from(changes).to_string(),))?
+        .unbind(),
+    )
+  }
+
+  /// Update values of this setup from a partial config
+  ///
+  /// The patch is merged into the config of this setup section by section, e.g.
+  /// ``{"pump": {"waist_um": 80}}`` only changes the pump waist. Values are parsed
+  /// as by :meth:`from_yaml`, so ``"auto"`` and elliptical waists can be used.
+  /// Fields that are calculated automatically (see :attr:`auto_fields`) and not set
+  /// by the patch are recalculated.
+  ///
+  /// Raises :class:`ConfigError` with the ``path`` of the offending value if the
+  /// patched config is not valid, in which case the setup is unchanged.
+  ///
+  /// Parameters
+  /// ----------
+  /// patch : dict
+  ///     The values to change, nested by config section
+  ///
+  /// Returns
+  /// -------
+  /// :class:`SPDC`
+  ///     This setup, updated in place
+  pub fn apply_patch<'py>(
+    mut slf: PyRefMut<'py, Self>,
+    patch: &Bound<'py, PyAny>,
+  ) -> PyResult<PyRefMut<'py, Self>> {
+    let text: String = slf
+      .py()
+      .import_bound("json")?
+      .call_method1("dumps", (patch,))?
+      .extract()?;
+    let patch: serde_json::Value = serde_json::from_str(&text).map_err(PyConfigError::from)?;
+    let serde_json::Value::Object(fields) = &patch else {
+      return Err(
+        PyConfigError::new("The patch must be a dict of config sections and values".into()).into(),
+      );
+    };
+    let auto = slf.2.unless_set_by(fields);
+    let mut config = serde_json::to_value(slf.config_value(true)).map_err(PySpdcError::from)?;
+    diff::merge(&mut config, patch);
+    let mut config = serde_yaml::to_value(config).expect("json values always convert");
+    auto.mark(&mut config);
+    *slf = config::parse_config_value(config)?;
+    Ok(slf)
+  }
+
+  /// Recalculate the fields that are calculated automatically
+  ///
+  /// Use this after changing other values, e.g. the signal wavelength, to get the
+  /// setup the config would describe when written with ``preserve_auto=True``.
+  /// See :attr:`auto_fields`.
+  ///
+  /// Returns
+  /// -------
+  /// :class:`SPDC`
+  ///     This setup, updated in place
+  pub fn resolve_auto(mut slf: PyRefMut<'_, Self>) -> PyResult<PyRefMut<'_, Self>> {
+    let value = slf.exact_config_with_auto();
+    *slf = config::parse_config_value(value)?;
+    Ok(slf)
+  }
+
   /// Get the optimum crystal theta
   pub fn optimum_crystal_theta(slf: PyRef<'_, Self>) -> f64 {
     *(slf.0.optimum_crystal_theta() / DEG)
@@ -550,6 +989,189 @@ impl SPDC {
     (dk.x, dk.y, dk.z)
   }
 
+  /// Calculate the temperature tuning curve of the phasematching
+  ///
+  /// The phase mismatch along z is computed at the central signal and idler
+  /// frequencies for the current poling period, and the relative phasematching
+  /// efficiency is the corresponding sinc² acceptance, `sinc²(Δk_z L / 2)`.
+  ///
+  /// Parameters
+  /// ----------
+  /// t_min_c : float
+  ///     The lowest temperature in degrees Celsius
+  /// t_max_c : float
+  ///     The highest temperature in degrees Celsius
+  /// n : int
+  ///     The number of temperatures to sample
+  ///
+  /// Returns
+  /// -------
+  /// `dict`
+  ///     A dictionary with the keys:
+  ///
+  ///     - ``temperature_c``: `numpy.ndarray` of `float`, the sampled temperatures
+  ///     - ``delta_k_z``: `numpy.ndarray` of `float`, the phase mismatch along z in rad/m
+  ///     - ``efficiency``: `numpy.ndarray` of `float`, the relative (sinc²) phasematching efficiency
+  ///     - ``fwhm_c``: `float` or `None`, the FWHM of the acceptance in degrees Celsius,
+  ///       or `None` if it does not fall below half maximum within the range
+  pub fn temperature_tuning_curve<'py>(
+    &self,
+    py: Python<'py>,
+    t_min_c: f64,
+    t_max_c: f64,
+    n: usize,
+  ) -> PyResult<Bound<'py, PyDict>> {
+    let temperatures: Vec<f64> = ::spdcalc::utils::Steps(t_min_c, t_max_c, n)
+      .into_iter()
+      .collect();
+    let length = *(self.0.crystal_setup.length / M);
+    let delta_k: Vec<f64> = temperatures.iter().map(|&t| self.delta_k_z_at(t)).collect();
+    let efficiency: Vec<f64> = delta_k
+      .iter()
+      .map(|dk| {
+        let x = dk * length / 2.;
+        if x == 0. {
+          1.
+        } else {
+          (x.sin() / x).powi(2)
+        }
+      })
+      .collect();
+
+    let dict = PyDict::new_bound(py);
+    dict.set_item("fwhm_c", crate::math::fwhm(&temperatures, &efficiency))?;
+    dict.set_item("temperature_c", PyArray1::from_vec_bound(py, temperatures))?;
+    dict.set_item("delta_k_z", PyArray1::from_vec_bound(py, delta_k))?;
+    dict.set_item("efficiency", PyArray1::from_vec_bound(py, efficiency))?;
+    Ok(dict)
+  }
+
+  /// Find the crystal temperature where the setup is perfectly phasematched
+  ///
+  /// Solves `Δk_z = 0` at the central signal and idler frequencies for the
+  /// current poling period. If there are several solutions within the search
+  /// range, the one closest to the current crystal temperature is returned.
+  ///
+  /// Parameters
+  /// ----------
+  /// t_min_c : float, optional
+  ///     The lowest temperature to search, in degrees Celsius. Defaults to 0.
+  /// t_max_c : float, optional
+  ///     The highest temperature to search, in degrees Celsius. Defaults to 200.
+  ///
+  /// Returns
+  /// -------
+  /// `float`
+  ///     The optimum temperature in degrees Celsius
+  #[pyo3(signature = (t_min_c = 0., t_max_c = 200.))]
+  pub fn optimum_temperature(&self, t_min_c: f64, t_max_c: f64) -> PyResult<f64> {
+    // bracket the sign changes of delta_k_z, then bisect the closest one
+    const SAMPLES: usize = 201;
+    let temperatures: Vec<f64> = ::spdcalc::utils::Steps(t_min_c, t_max_c, SAMPLES)
+      .into_iter()
+      .collect();
+    let delta_k: Vec<f64> = temperatures.iter().map(|&t| self.delta_k_z_at(t)).collect();
+    let current = self.crystal_temperature_c();
+    let (mut lo, mut hi) = (0..SAMPLES - 1)
+      .filter(|&i| delta_k[i] == 0. || delta_k[i].signum() != delta_k[i + 1].signum())
+      .map(|i| (temperatures[i], temperatures[i + 1]))
+      .min_by(|a, b| {
+        let distance = |(lo, hi): &(f64, f64)| ((lo + hi) / 2. - current).abs();
+        distance(a).total_cmp(&distance(b))
+      })
+      .ok_or_else(|| {
+        PhasematchingError::new_err(format!(
+          "No phasematching temperature found between {} and {} degrees Celsius",
+          t_min_c, t_max_c
+        ))
+      })?;
+    let lo_sign = self.delta_k_z_at(lo).signum();
+    for _ in 0..100 {
+      let mid = (lo + hi) / 2.;
+      if self.delta_k_z_at(mid).signum() == lo_sign {
+        lo = mid;
+      } else {
+        hi = mid;
+      }
+      if (hi - lo).abs() < 1e-9 {
+        break;
+      }
+    }
+    Ok((lo + hi) / 2.)
+  }
+
+  /// Calculate the phasematching function over a range of frequencies
+  ///
+  /// This excludes the pump spectral envelope (see :func:`pump_spectral_amplitude`),
+  /// so the two can be inspected independently.
+  ///
+  /// Parameters
+  /// ----------
+  /// si_range : SIRange
+  ///     Range of signal and idler frequencies
+  /// mode : str, optional
+  ///     Which phasematching function to calculate:
+  ///
+  ///     - ``"plane_wave"``: the sinc phasematching of plane waves, with the
+  ///       transverse gaussian pump overlap (no fiber coupling)
+  ///     - ``"fiber_coupled"`` (default): the coincidences phasematching amplitude
+  ///       including the fiber coupling overlap
+  ///     - ``"singles"``: the singles phasematching *intensity* including the fiber coupling overlap
+  /// integrator : Integrator, optional
+  ///     The integrator to use for the fiber coupled modes, which defaults to a simple Simpson's rule
+  ///
+  /// Returns
+  /// -------
+  /// `numpy.ndarray` of `complex` (`float` for ``"singles"``)
+  ///     The phasematching function, shaped like the range
+  #[pyo3(signature = (si_range, mode = PhasematchMode::FiberCoupled, integrator = None))]
+  pub fn phasematch_range(
+    &self,
+    py: Python<'_>,
+    si_range: SIRange,
+    mode: PhasematchMode,
+    integrator: Option<crate::Integrator>,
+  ) -> PyResult<PyObject> {
+    use ::spdcalc::{PerMeter3, PerMeter4};
+    let shape = si_range.shape();
+    let integrator = integrator.unwrap_or_default().0;
+    let spdc = &self.0;
+    let pairs = si_range.into_signal_idler_par_iterator();
+    let values = match mode {
+      PhasematchMode::PlaneWave => {
+        let values: Vec<_> = py.allow_threads(|| {
+          pairs
+            .map(|(ws, wi)| *(::spdcalc::phasematch_sinc(ws, wi, spdc) / PerMeter4::new(1.0)))
+            .collect()
+        });
+        to_shaped_pyarray(py, values, shape)?.into_any()
+      }
+      PhasematchMode::FiberCoupled => {
+        let values: Vec<_> = py.allow_threads(|| {
+          pairs
+            .map(|(ws, wi)| {
+              *(::spdcalc::phasematch_fiber_coupling(ws, wi, spdc, integrator)
+                / PerMeter4::new(1.0))
+            })
+            .collect()
+        });
+        to_shaped_pyarray(py, values, shape)?.into_any()
+      }
+      PhasematchMode::Singles => {
+        let values: Vec<_> = py.allow_threads(|| {
+          pairs
+            .map(|(ws, wi)| {
+              *(::spdcalc::phasematch_singles_fiber_coupling(ws, wi, spdc, integrator)
+                / PerMeter3::new(1.0))
+            })
+            .collect()
+        });
+        to_shaped_pyarray(py, values, shape)?.into_any()
+      }
+    };
+    Ok(values.unbind())
+  }
+
   /// Calculate the coincidence counts
   ///
   /// Parameters
@@ -566,13 +1188,13 @@ impl SPDC {
   #[pyo3(signature = (si_range, integrator = None))]
   pub fn counts_coincidences(
     &self,
+    py: Python<'_>,
     si_range: SIRange,
     integrator: Option<crate::Integrator>,
   ) -> PyResult<f64> {
-    let counts = self.0.counts_coincidences(
-      ::spdcalc::FrequencySpace::try_from(si_range)?,
-      integrator.unwrap_or_default().0,
-    );
+    let range = ::spdcalc::FrequencySpace::try_from(si_range)?;
+    let integrator = integrator.unwrap_or_default().0;
+    let counts = py.allow_threads(|| pump::counts_coincidences(self, range, integrator));
     Ok(*(counts * S))
   }
 
@@ -592,13 +1214,13 @@ impl SPDC {
   #[pyo3(signature = (si_range, integrator = None))]
   pub fn counts_singles_signal(
     &self,
+    py: Python<'_>,
     si_range: SIRange,
     integrator: Option<crate::Integrator>,
   ) -> PyResult<f64> {
-    let counts = self.0.counts_singles_signal(
-      ::spdcalc::FrequencySpace::try_from(si_range)?,
-      integrator.unwrap_or_default().0,
-    );
+    let range = ::spdcalc::FrequencySpace::try_from(si_range)?;
+    let integrator = integrator.unwrap_or_default().0;
+    let counts = py.allow_threads(|| pump::counts_singles_signal(self, range, integrator));
     Ok(*(counts * S))
   }
 
@@ -618,13 +1240,13 @@ impl SPDC {
   #[pyo3(signature = (si_range, integrator = None))]
   pub fn counts_singles_idler(
     &self,
+    py: Python<'_>,
     si_range: SIRange,
     integrator: Option<crate::Integrator>,
   ) -> PyResult<f64> {
-    let counts = self.0.counts_singles_idler(
-      ::spdcalc::FrequencySpace::try_from(si_range)?,
-      integrator.unwrap_or_default().0,
-    );
+    let range = ::spdcalc::FrequencySpace::try_from(si_range)?;
+    let integrator = integrator.unwrap_or_default().0;
+    let counts = py.allow_threads(|| pump::counts_singles_idler(self, range, integrator));
     Ok(*(counts * S))
   }
 
@@ -644,15 +1266,14 @@ impl SPDC {
   #[pyo3(signature = (si_range, integrator = None))]
   pub fn efficiencies(
     &self,
+    py: Python<'_>,
     si_range: SIRange,
     integrator: Option<crate::Integrator>,
   ) -> PyResult<HashMap<String, f64>> {
-    let effs: HashMap<String, f64> = self
-      .0
-      .efficiencies(
-        ::spdcalc::FrequencySpace::try_from(si_range)?,
-        integrator.unwrap_or_default().0,
-      )
+    let range = ::spdcalc::FrequencySpace::try_from(si_range)?;
+    let integrator = integrator.unwrap_or_default().0;
+    let effs: HashMap<String, f64> = py
+      .allow_threads(|| pump::efficiencies(self, range, integrator))
       .into();
 
     Ok(effs)
@@ -674,13 +1295,13 @@ impl SPDC {
   #[pyo3(signature = (si_range, integrator = None))]
   pub fn hom_visibility(
     &self,
+    py: Python<'_>,
     si_range: SIRange,
     integrator: Option<crate::Integrator>,
   ) -> PyResult<Visibility> {
-    let (time, v): (Time, f64) = self.0.hom_visibility(
-      ::spdcalc::FrequencySpace::try_from(si_range)?,
-      integrator.unwrap_or_default().0,
-    );
+    let range = ::spdcalc::FrequencySpace::try_from(si_range)?;
+    let integrator = integrator.unwrap_or_default().0;
+    let (time, v): (Time, f64) = py.allow_threads(|| pump::hom_visibility(self, range, integrator));
 
     let mut vis = HashMap::new();
     vis.insert("time".to_string(), *(time / S));
@@ -692,8 +1313,8 @@ impl SPDC {
   ///
   /// Parameters
   /// ----------
-  /// time_delays : list of floats
-  ///     The time delays in seconds
+  /// time_delays : list of floats or numpy.ndarray
+  ///     The time delays in seconds, as a one dimensional sequence
   /// si_range : SIRange
   ///     Range of signal and idler frequencies
   /// integrator : Integrator, optional
@@ -706,16 +1327,15 @@ impl SPDC {
   #[pyo3(signature = (time_delays, si_range, integrator = None))]
   pub fn hom_rate_series(
     &self,
-    time_delays: Vec<f64>,
+    py: Python<'_>,
+    time_delays: FloatArray<'_>,
     si_range: SIRange,
     integrator: Option<crate::Integrator>,
   ) -> PyResult<Vec<f64>> {
-    let time_delays: Vec<_> = time_delays.into_iter().map(|t| t * S).collect();
-    let rates = self.0.hom_rate_series(
-      time_delays,
-      ::spdcalc::FrequencySpace::try_from(si_range)?,
-      integrator.unwrap_or_default().0,
-    );
+    let time_delays = time_delays.map_series("time delays", |t| t * S)?;
+    let range = ::spdcalc::FrequencySpace::try_from(si_range)?;
+    let integrator = integrator.unwrap_or_default().0;
+    let rates = py.allow_threads(|| pump::hom_rate_series(self, time_delays, range, integrator));
     Ok(rates)
   }
 
@@ -735,15 +1355,15 @@ impl SPDC {
   #[pyo3(signature = (si_range, integrator = None))]
   pub fn hom_two_source_visibilities(
     &self,
+    py: Python<'_>,
     si_range: SIRange,
     integrator: Option<crate::Integrator>,
   ) -> PyResult<HashMap<String, Visibility>> {
-    let vis: HashMap<String, (Time, f64)> = self
-      .0
-      .hom_two_source_visibilities(
-        ::spdcalc::FrequencySpace::try_from(si_range)?,
-        integrator.unwrap_or_default().0,
-      )
+    let range = ::spdcalc::FrequencySpace::try_from(si_range)?;
+    let integrator = integrator.unwrap_or_default().0;
+    pump::require_gaussian(self, "Two-source HOM")?;
+    let vis: HashMap<String, (Time, f64)> = py
+      .allow_threads(|| self.0.hom_two_source_visibilities(range, integrator))
       .into();
     let vis: HashMap<String, Visibility> = vis
       .into_iter()
@@ -761,8 +1381,8 @@ impl SPDC {
   ///
   /// Parameters
   /// ----------
-  /// time_delays : list of floats
-  ///     The time delays in seconds
+  /// time_delays : list of floats or numpy.ndarray
+  ///     The time delays in seconds, as a one dimensional sequence
   /// si_range : SIRange
   ///     Range of signal and idler frequencies
   /// integrator : Integrator, optional
@@ -775,18 +1395,21 @@ impl SPDC {
   #[pyo3(signature = (time_delays, si_range, integrator = None))]
   pub fn hom_two_source_rate_series(
     &self,
-    time_delays: Vec<f64>,
+    py: Python<'_>,
+    time_delays: FloatArray<'_>,
     si_range: SIRange,
     integrator: Option<crate::Integrator>,
   ) -> PyResult<HashMap<String, Vec<f64>>> {
-    let time_delays: Vec<_> = time_delays.into_iter().map(|t| t * S).collect();
-    let rates: HashMap<String, Vec<f64>> = self
-      .0
-      .hom_two_source_rate_series(
-        time_delays,
-        ::spdcalc::FrequencySpace::try_from(si_range)?,
-        integrator.unwrap_or_default().0,
-      )
+    let time_delays = time_delays.map_series("time delays", |t| t * S)?;
+    let range = ::spdcalc::FrequencySpace::try_from(si_range)?;
+    let integrator = integrator.unwrap_or_default().0;
+    pump::require_gaussian(self, "Two-source HOM")?;
+    let rates: HashMap<String, Vec<f64>> = py
+      .allow_threads(|| {
+        self
+          .0
+          .hom_two_source_rate_series(time_delays, range, integrator)
+      })
       .into();
     Ok(rates)
   }
@@ -804,9 +1427,600 @@ impl SPDC {
   ///     The joint spectrum object
   #[pyo3(signature = (integrator = None))]
   pub fn joint_spectrum(&self, integrator: Option<Integrator>) -> JointSpectrum {
-    self
+    JointSpectrum::from_setup(self.clone(), integrator.unwrap_or_default())
+  }
+}
+
+/// Which phasematching function to calculate
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum PhasematchMode {
+  PlaneWave,
+  FiberCoupled,
+  Singles,
+}
+
+impl<'py> FromPyObject<'py> for PhasematchMode {
+  fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+    match ob.extract::<String>()?.as_str() {
+      "plane_wave" => Ok(PhasematchMode::PlaneWave),
+      "fiber_coupled" => Ok(PhasematchMode::FiberCoupled),
+      "singles" => Ok(PhasematchMode::Singles),
+      other => Err(PyValueError::new_err(format!(
+        "Unknown phasematching mode '{}'. Expected one of: plane_wave, fiber_coupled, singles",
+        other
+      ))),
+    }
+  }
+}
+
+impl SPDC {
+  /// The config as written by `to_yaml` and friends
+  fn written_config(&self, preserve_auto: bool) -> serde_yaml::Value {
+    let mut value = self.config_value(false);
+    if preserve_auto {
+      self.2.mark(&mut value);
+    }
+    value
+  }
+
+  /// The full precision config (including the pump spectrum) that equality
+  /// and keys are based on
+  ///
+  /// `-0.0` is written as `0.0`, so that values that compare equal also have
+  /// the same key.
+  fn canonical_config(&self) -> Result<serde_json::Value, PySpdcError> {
+    let mut value = serde_json::to_value(self.config_value(true))?;
+    without_negative_zeros(&mut value);
+    Ok(value)
+  }
+
+  /// The full precision JSON config and the paths of the auto fields, from which
+  /// `from_state` restores the setup exactly
+  pub(crate) fn state(&self) -> Result<State, PySpdcError> {
+    Ok((
+      serde_json::to_string(&self.config_value(true))?,
+      self.2.paths(),
+    ))
+  }
+
+  /// Restore a setup from its full precision JSON config and the paths of its
+  /// auto fields, as written by `state`
+  pub(crate) fn from_state<S: AsRef<str>>(json: &str, auto: &[S]) -> PyResult<Self> {
+    let mut spdc = Self::from_json(json)?;
+    spdc.2 = config::AutoFields::from_paths(auto)?;
+    Ok(spdc)
+  }
+
+  /// The full precision config, with `auto` for the auto fields
+  fn exact_config_with_auto(&self) -> serde_yaml::Value {
+    let mut value = self.config_value(true);
+    self.2.mark(&mut value);
+    value
+  }
+
+  /// The sign of the poling that phasematches this setup, which spdcalc assigns
+  /// when loading a config
+  fn phasematching_poling_sign(&self) -> spdcalc::Sign {
+    PeriodicPoling::compute_sign(&self.0.signal, &self.0.pump, &self.0.crystal_setup)
+  }
+
+  /// Whether the sign of the poling is reversed from the one that phasematches
+  pub(crate) fn poling_is_reversed(&self) -> bool {
+    match self.0.pp {
+      PeriodicPoling::Off => false,
+      PeriodicPoling::On { sign, .. } => sign != self.phasematching_poling_sign(),
+    }
+  }
+
+  /// The phase mismatch along z (rad/m) at the central signal and idler
+  /// frequencies, with the crystal at the given temperature
+  pub(crate) fn delta_k_z_at(&self, temperature_c: f64) -> f64 {
+    let mut spdc = self.0.clone();
+    spdc.crystal_setup.temperature = from_celsius_to_kelvin(temperature_c);
+    let dk = spdc.delta_k(spdc.signal.frequency(), spdc.idler.frequency());
+    (dk * M / RAD).z
+  }
+
+  /// The config for this setup without rounding to significant figures
+  ///
+  /// `SPDCConfig::from` rounds values for readability. This keeps full precision
+  /// so that the setup can be reconstructed exactly (eg: when pickling).
+  pub(crate) fn exact_config(&self) -> SPDCConfig {
+    let spdc = &self.0;
+    let mut config = SPDCConfig::from(spdc.clone());
+
+    config.crystal.phi_deg = *(spdc.crystal_setup.phi / DEG);
+    config.crystal.theta_deg = AutoCalcParam::Param(*(spdc.crystal_setup.theta / DEG));
+    config.crystal.length_um = *(spdc.crystal_setup.length / (MICRO * M));
+    config.crystal.temperature_c = from_kelvin_to_celsius(spdc.crystal_setup.temperature);
+
+    config.pump.wavelength_nm = *(spdc.pump.vacuum_wavelength() / (NANO * M));
+    config.pump.waist_um = *(spdc.pump.waist().x / (MICRO * M));
+    config.pump.bandwidth_nm = *(spdc.pump_bandwidth / (NANO * M));
+    config.pump.average_power_mw = *(spdc.pump_average_power / MILLIW);
+
+    config.signal.wavelength_nm = *(spdc.signal.vacuum_wavelength() / (NANO * M));
+    config.signal.phi_deg = *(spdc.signal.phi() / DEG);
+    config.signal.theta_deg = Some(*(spdc.signal.theta_internal() / DEG));
+    config.signal.waist_um = *(spdc.signal.waist().x / (MICRO * M));
+    config.signal.waist_position_um =
+      AutoCalcParam::Param(*(spdc.signal_waist_position / (MICRO * M)));
+
+    if let AutoCalcParam::Param(idler) = &mut config.idler {
+      idler.wavelength_nm = *(spdc.idler.vacuum_wavelength() / (NANO * M));
+      idler.phi_deg = *(spdc.idler.phi() / DEG);
+      idler.theta_deg = Some(*(spdc.idler.theta_internal() / DEG));
+      idler.waist_um = *(spdc.idler.waist().x / (MICRO * M));
+    }
+
+    if let (
+      PeriodicPolingConfig::Config {
+        poling_period_um, ..
+      },
+      PeriodicPoling::On { period, .. },
+    ) = (&mut config.periodic_poling, &spdc.pp)
+    {
+      *poling_period_um = AutoCalcParam::Param(*(*period / (MICRO * M)));
+    }
+
+    config.deff_pm_per_volt = *(spdc.deff / (PICO * M / V));
+    config
+  }
+}
+
+/// Replace every `-0.0` in a json value by `0.0`
+fn without_negative_zeros(value: &mut serde_json::Value) {
+  use serde_json::Value;
+  match value {
+    Value::Number(n) if n.as_f64() == Some(0.) => *n = serde_json::Number::from(0),
+    Value::Array(values) => values.iter_mut().for_each(without_negative_zeros),
+    Value::Object(values) => values.values_mut().for_each(without_negative_zeros),
+    _ => {}
+  }
+}
+
+/// Compare two json values, allowing numbers to differ within tolerance
+pub(crate) fn json_is_close(
+  a: &serde_json::Value,
+  b: &serde_json::Value,
+  rtol: f64,
+  atol: f64,
+) -> bool {
+  use serde_json::Value;
+  match (a, b) {
+    (Value::Number(a), Value::Number(b)) => {
+      let (a, b) = (a.as_f64().unwrap(), b.as_f64().unwrap());
+      (a - b).abs() <= (rtol * a.abs().max(b.abs())).max(atol)
+    }
+    (Value::Array(a), Value::Array(b)) => {
+      a.len() == b.len()
+        && a
+          .iter()
+          .zip(b)
+          .all(|(a, b)| json_is_close(a, b, rtol, atol))
+    }
+    (Value::Object(a), Value::Object(b)) => {
+      a.len() == b.len()
+        && a
+          .iter()
+          .all(|(key, a)| b.get(key).is_some_and(|b| json_is_close(a, b, rtol, atol)))
+    }
+    (a, b) => a == b,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use spdcalc::{IdlerConfig, PMType};
+
+  const YAML: &str = r#"
+crystal:
+  kind: KTP
+  pm_type: e->eo
+  phi_deg: 1.5
+  theta_deg: 90
+  length_um: 2500
+  temperature_c: 35
+  counter_propagation: true
+pump:
+  wavelength_nm: 780
+  waist_um: 120
+  bandwidth_nm: 2.5
+  average_power_mw: 300
+  spectrum_threshold: 0.005
+signal:
+  wavelength_nm: 1560
+  phi_deg: 2.5
+  theta_deg: 0.5
+  waist_um: 90
+  waist_position_um: -400
+idler:
+  wavelength_nm: 1560
+  phi_deg: 182.5
+  theta_deg: 0.25
+  waist_um: 80
+  waist_position_um: -300
+periodic_poling:
+  poling_period_um: 46.5
+deff_pm_per_volt: 7.5
+"#;
+
+  fn assert_close(actual: f64, expected: f64) {
+    assert!(
+      (actual - expected).abs() <= 1e-9 * expected.abs().max(1e-12),
+      "{} != {}",
+      actual,
+      expected
+    );
+  }
+
+  fn spdc() -> SPDC {
+    SPDC::from_yaml(YAML).unwrap()
+  }
+
+  fn signed_poling_period_um(spdc: &SPDC) -> f64 {
+    *(spdc.0.pp.signed_period() / MICRO / M)
+  }
+
+  fn config(spdc: &SPDC) -> SPDCConfig {
+    let yaml = spdc.to_yaml(false).map_err(|e| e.0).unwrap();
+    serde_yaml::from_str(&yaml).unwrap()
+  }
+
+  fn param<T: Copy>(value: &AutoCalcParam<T>) -> T {
+    match value {
+      AutoCalcParam::Param(value) => *value,
+      AutoCalcParam::Auto(_) => panic!("expected a value, got auto"),
+    }
+  }
+
+  fn idler(config: &SPDCConfig) -> &IdlerConfig {
+    match &config.idler {
+      AutoCalcParam::Param(idler) => idler,
+      AutoCalcParam::Auto(_) => panic!("expected an idler config, got auto"),
+    }
+  }
+
+  #[test]
+  fn getters_match_config() {
+    let spdc = spdc();
+    assert_eq!(spdc.crystal_kind(), CrystalType::KTP);
+    assert_eq!(spdc.crystal_pm_type(), PMType::Type2_e_eo);
+    assert_close(spdc.crystal_phi_deg(), 1.5);
+    assert_close(spdc.crystal_theta_deg(), 90.);
+    assert_close(spdc.crystal_length_um(), 2500.);
+    assert_close(spdc.crystal_temperature_c(), 35.);
+    assert!(spdc.counter_propagation());
+
+    assert_close(spdc.pump_wavelength_nm(), 780.);
+    assert_close(spdc.pump_waist_nm(), 120. * 1e3);
+    assert_close(spdc.pump_bandwidth_nm(), 2.5);
+    assert_close(spdc.pump_average_power_mw(), 300.);
+    assert_close(spdc.pump_spectrum_threshold(), 0.005);
+
+    assert_close(spdc.signal_wavelength_nm(), 1560.);
+    assert_close(spdc.signal_phi_deg(), 2.5);
+    assert_close(spdc.signal_theta_deg(), 0.5);
+    assert_close(spdc.signal_waist_um(), 90.);
+    assert_close(spdc.signal_waist_position_um(), -400.);
+
+    assert_close(spdc.idler_wavelength_nm(), 1560.);
+    assert_close(spdc.idler_phi_deg(), 182.5);
+    assert_close(spdc.idler_theta_deg(), 0.25);
+    assert_close(spdc.idler_waist_um(), 80.);
+    assert_close(spdc.idler_waist_position_um(), -300.);
+
+    assert_close(spdc.poling_period_um().unwrap(), 46.5);
+    assert_close(spdc.deff_pm_per_volt(), 7.5);
+  }
+
+  #[test]
+  fn config_matches_getters() {
+    let spdc = spdc();
+    let cfg = config(&spdc);
+    assert_close(cfg.crystal.phi_deg, spdc.crystal_phi_deg());
+    assert_close(param(&cfg.crystal.theta_deg), spdc.crystal_theta_deg());
+    assert_close(cfg.crystal.length_um, spdc.crystal_length_um());
+    assert_close(cfg.crystal.temperature_c, spdc.crystal_temperature_c());
+    assert_eq!(cfg.crystal.counter_propagation, spdc.counter_propagation());
+
+    assert_close(cfg.pump.wavelength_nm, spdc.pump_wavelength_nm());
+    assert_close(cfg.pump.waist_um * 1e3, spdc.pump_waist_nm());
+    assert_close(cfg.pump.bandwidth_nm, spdc.pump_bandwidth_nm());
+    assert_close(cfg.pump.average_power_mw, spdc.pump_average_power_mw());
+    assert_close(
+      cfg.pump.spectrum_threshold.unwrap(),
+      spdc.pump_spectrum_threshold(),
+    );
+
+    assert_close(cfg.signal.wavelength_nm, spdc.signal_wavelength_nm());
+    assert_close(cfg.signal.phi_deg, spdc.signal_phi_deg());
+    assert_close(cfg.signal.theta_deg.unwrap(), spdc.signal_theta_deg());
+    assert_close(cfg.signal.waist_um, spdc.signal_waist_um());
+    assert_close(
+      param(&cfg.signal.waist_position_um),
+      spdc.signal_waist_position_um(),
+    );
+
+    let idler = idler(&cfg);
+    assert_close(idler.wavelength_nm, spdc.idler_wavelength_nm());
+    assert_close(idler.phi_deg, spdc.idler_phi_deg());
+    assert_close(idler.theta_deg.unwrap(), spdc.idler_theta_deg());
+    assert_close(idler.waist_um, spdc.idler_waist_um());
+    assert_close(
+      param(&idler.waist_position_um),
+      spdc.idler_waist_position_um(),
+    );
+
+    match &cfg.periodic_poling {
+      PeriodicPolingConfig::Config {
+        poling_period_um, ..
+      } => assert_close(param(poling_period_um), spdc.poling_period_um().unwrap()),
+      PeriodicPolingConfig::Off => panic!("expected periodic poling"),
+    }
+    assert_close(cfg.deff_pm_per_volt, spdc.deff_pm_per_volt());
+  }
+
+  #[test]
+  fn setters_round_trip_through_config() {
+    let mut spdc = spdc();
+    spdc.set_crystal_phi_deg(2.);
+    spdc.set_crystal_theta_deg(80.);
+    spdc.set_crystal_length_um(1000.);
+    spdc.set_crystal_temperature_c(45.);
+    spdc.set_counter_propagation(false);
+
+    spdc.set_pump_waist_nm(150_000.);
+    spdc.set_pump_bandwidth_nm(1.5);
+    spdc.set_pump_average_power_mw(12.5);
+    spdc.set_pump_spectrum_threshold(0.02);
+
+    spdc.set_signal_phi_deg(3.);
+    spdc.set_signal_theta_deg(1.25);
+    spdc.set_signal_waist_um(70.);
+    spdc.set_signal_waist_position_um(-250.);
+
+    spdc.set_idler_phi_deg(183.);
+    spdc.set_idler_theta_deg(1.5);
+    spdc.set_idler_waist_um(60.);
+    spdc.set_idler_waist_position_um(-150.);
+
+    spdc.set_poling_period_um(Some(40.5));
+    spdc.set_deff_pm_per_volt(2.5);
+
+    let cfg = config(&spdc);
+    assert_close(cfg.crystal.phi_deg, 2.);
+    assert_close(param(&cfg.crystal.theta_deg), 80.);
+    assert_close(cfg.crystal.length_um, 1000.);
+    assert_close(cfg.crystal.temperature_c, 45.);
+    assert!(!cfg.crystal.counter_propagation);
+
+    assert_close(cfg.pump.waist_um, 150.);
+    assert_close(cfg.pump.bandwidth_nm, 1.5);
+    assert_close(cfg.pump.average_power_mw, 12.5);
+    assert_close(cfg.pump.spectrum_threshold.unwrap(), 0.02);
+
+    assert_close(cfg.signal.phi_deg, 3.);
+    assert_close(cfg.signal.theta_deg.unwrap(), 1.25);
+    assert_close(cfg.signal.waist_um, 70.);
+    assert_close(param(&cfg.signal.waist_position_um), -250.);
+
+    let idler = idler(&cfg);
+    assert_close(idler.phi_deg, 183.);
+    assert_close(idler.theta_deg.unwrap(), 1.5);
+    assert_close(idler.waist_um, 60.);
+    assert_close(param(&idler.waist_position_um), -150.);
+
+    match &cfg.periodic_poling {
+      PeriodicPolingConfig::Config {
+        poling_period_um, ..
+      } => assert_close(param(poling_period_um), 40.5),
+      PeriodicPolingConfig::Off => panic!("expected periodic poling"),
+    }
+    assert_close(cfg.deff_pm_per_volt, 2.5);
+  }
+
+  #[test]
+  fn wavelength_and_frequency_setters_agree() {
+    let mut spdc = spdc();
+    let omega = spdc.signal_frequency_rad_per_s();
+    spdc.set_signal_wavelength_nm(1500.);
+    assert_close(config(&spdc).signal.wavelength_nm, 1500.);
+    spdc.set_signal_frequency_rad_per_s(omega);
+    assert_close(config(&spdc).signal.wavelength_nm, 1560.);
+
+    let omega = spdc.pump_frequency_rad_per_s();
+    spdc.set_pump_wavelength_nm(770.);
+    assert_close(config(&spdc).pump.wavelength_nm, 770.);
+    spdc.set_pump_frequency_rad_per_s(omega);
+    assert_close(config(&spdc).pump.wavelength_nm, 780.);
+
+    let omega = spdc.idler_frequency_rad_per_s();
+    spdc.set_idler_wavelength_nm(1600.);
+    assert_close(idler(&config(&spdc)).wavelength_nm, 1600.);
+    spdc.set_idler_frequency_rad_per_s(omega);
+    assert_close(idler(&config(&spdc)).wavelength_nm, 1560.);
+  }
+
+  const PP_YAML: &str = r#"
+crystal:
+  kind: KTP
+  pm_type: e->eo
+  phi_deg: 0
+  theta_deg: 90
+  length_um: 14000
+  temperature_c: 40
+pump:
+  wavelength_nm: 775
+  waist_um: 200
+  bandwidth_nm: 0.5
+  average_power_mw: 300
+signal:
+  wavelength_nm: 1550
+  phi_deg: 0
+  theta_external_deg: 0
+  waist_um: 100
+  waist_position_um: auto
+idler: auto
+periodic_poling:
+  poling_period_um: auto
+deff_pm_per_volt: 7.6
+"#;
+
+  #[test]
+  fn negative_poling_period_reverses_the_sign() {
+    let mut spdc = SPDC::from_yaml(PP_YAML).unwrap();
+    assert!(spdc.delta_k_z_at(40.).abs() < 1e-6);
+    let signed = signed_poling_period_um(&spdc);
+    let period = spdc.poling_period_um().unwrap();
+    assert!(period > 0.);
+    spdc.set_poling_period_um(Some(-period));
+    assert_close(signed_poling_period_um(&spdc), -signed);
+    assert_close(spdc.poling_period_um().unwrap(), -period);
+    assert!(spdc.delta_k_z_at(40.).abs() > 1.);
+    spdc.set_poling_period_um(Some(period));
+    assert_close(signed_poling_period_um(&spdc), signed);
+    assert!(spdc.delta_k_z_at(40.).abs() < 1e-6);
+  }
+
+  #[test]
+  fn reversed_poling_survives_configs_and_state() {
+    let eq = |a: &SPDC, b: &SPDC| a.__eq__(b).map_err(|e| e.0).unwrap();
+    let phasematched = SPDC::from_yaml(PP_YAML).unwrap();
+    let mut reversed = phasematched.clone();
+    reversed.0.pp = reversed
       .0
-      .joint_spectrum(integrator.unwrap_or_default().0)
-      .into()
+      .pp
+      .clone()
+      .with_period(-reversed.0.pp.signed_period());
+    assert!(!eq(&reversed, &phasematched));
+
+    let (json, auto) = reversed.state().map_err(|e| e.0).unwrap();
+    let restored = SPDC::from_state(&json, &auto).unwrap();
+    assert_eq!(
+      signed_poling_period_um(&restored),
+      signed_poling_period_um(&reversed)
+    );
+    assert!(eq(&restored, &reversed));
+
+    let yaml = reversed.to_yaml(false).map_err(|e| e.0).unwrap();
+    let loaded = SPDC::from_yaml(&yaml).unwrap();
+    // the yaml is rounded to significant figures
+    let (a, b) = (
+      signed_poling_period_um(&loaded),
+      signed_poling_period_um(&reversed),
+    );
+    assert!(loaded.poling_is_reversed());
+    assert!((a - b).abs() < 1e-3 * b.abs());
+  }
+
+  #[test]
+  fn optimum_temperature_solves_phasematching() {
+    let mut spdc = SPDC::from_yaml(PP_YAML).unwrap();
+    assert_close(spdc.optimum_temperature(0., 200.).unwrap(), 40.);
+    spdc.set_poling_period_um(spdc.poling_period_um().map(|p| p * 1.0001));
+    let t = spdc.optimum_temperature(0., 200.).unwrap();
+    assert!((t - 40.).abs() > 0.1);
+    assert!(spdc.delta_k_z_at(t).abs() < 1e-3);
+  }
+
+  #[test]
+  fn pump_average_power_is_in_milliwatts() {
+    let mut spdc = spdc();
+    spdc.set_pump_average_power_mw(1.);
+    assert_close(*(spdc.0.pump_average_power / W), 1e-3);
+  }
+
+  #[test]
+  fn equality_uses_full_precision() {
+    let eq = |a: &SPDC, b: &SPDC| a.__eq__(b).map_err(|e| e.0).unwrap();
+    let key = |s: &SPDC| s.key().map_err(|e| e.0).unwrap();
+    let a = spdc();
+    let mut b = a.clone();
+    assert!(eq(&a, &b));
+    assert_eq!(key(&a), key(&b));
+    b.set_crystal_length_um(a.crystal_length_um() + 0.4);
+    assert!(!eq(&a, &b));
+    assert!(a.is_close(&b, 1e-3, 0.).map_err(|e| e.0).unwrap());
+  }
+
+  #[test]
+  fn equal_setups_have_the_same_key() {
+    let eq = |a: &SPDC, b: &SPDC| a.__eq__(b).map_err(|e| e.0).unwrap();
+    let key = |s: &SPDC| s.key().map_err(|e| e.0).unwrap();
+    let mut a = spdc();
+    let mut b = a.clone();
+    let (x, y) = a.pump_waist_xy_nm();
+    b.set_pump_waist_xy_nm((x, y * 2.));
+    assert!(!eq(&a, &b));
+    a.set_pump_waist_xy_nm((x, y * 2.));
+    assert!(eq(&a, &b));
+    assert_eq!(key(&a), key(&b));
+    a.set_signal_phi_deg(0.);
+    b.set_signal_phi_deg(-0.);
+    assert!(eq(&a, &b));
+    assert_eq!(key(&a), key(&b));
+  }
+
+  #[test]
+  fn state_restores_the_setup() {
+    let spdc = SPDC::from_yaml(PP_YAML).unwrap();
+    let (json, auto) = spdc.state().map_err(|e| e.0).unwrap();
+    let restored = SPDC::from_state(&json, &auto).unwrap();
+    assert_eq!(restored.auto_fields(), spdc.auto_fields());
+    assert_eq!(restored.poling_period_um(), spdc.poling_period_um());
+    assert!(restored
+      .is_close(&spdc, 1e-12, 0.)
+      .map_err(|e| e.0)
+      .unwrap());
+  }
+
+  #[test]
+  fn auto_fields_are_remembered() {
+    let mut spdc = SPDC::from_yaml(PP_YAML).unwrap();
+    assert_eq!(
+      spdc.auto_fields(),
+      vec![
+        "periodic_poling.poling_period_um",
+        "signal.waist_position_um",
+        "idler",
+        "idler.waist_position_um"
+      ]
+    );
+    assert!(SPDC::from_yaml(YAML).unwrap().auto_fields().is_empty());
+
+    let yaml = spdc.to_yaml(true).map_err(|e| e.0).unwrap();
+    let config: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+    assert_eq!(config["idler"], "auto");
+    assert_eq!(config["periodic_poling"]["poling_period_um"], "auto");
+    assert_eq!(config["crystal"]["theta_deg"], 90.);
+    assert_eq!(
+      SPDC::from_yaml(&yaml).unwrap().auto_fields(),
+      spdc.auto_fields()
+    );
+
+    spdc.set_idler_waist_um(80.);
+    spdc.set_poling_period_um(Some(46.));
+    assert_eq!(
+      spdc.auto_fields(),
+      vec!["signal.waist_position_um", "idler.waist_position_um"]
+    );
+  }
+
+  #[test]
+  fn auto_fields_are_resolved_after_changes() {
+    let mut spdc = SPDC::from_yaml(PP_YAML).unwrap();
+    spdc.set_signal_wavelength_nm(1500.);
+    // the poling period and idler are only recalculated on request
+    assert_close(spdc.idler_wavelength_nm(), 1550.);
+    let resolved = config::parse_config_value(spdc.exact_config_with_auto()).unwrap();
+    let expected =
+      SPDC::from_yaml(&PP_YAML.replace("wavelength_nm: 1550", "wavelength_nm: 1500")).unwrap();
+    assert!(resolved
+      .is_close(&expected, 1e-9, 0.)
+      .map_err(|e| e.0)
+      .unwrap());
+    assert_eq!(resolved.auto_fields(), spdc.auto_fields());
   }
 }
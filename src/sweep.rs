@@ -0,0 +1,338 @@
+use crate::*;
+use numpy::PyArrayDyn;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::PyDict;
+use rayon::prelude::*;
+use spdcalc::FrequencySpace;
+
+/// A numeric SPDC field that can be swept or optimized, by property name
+pub(crate) struct Field {
+  pub(crate) name: &'static str,
+  pub(crate) get: fn(&SPDC) -> f64,
+  pub(crate) set: fn(&mut SPDC, f64),
+}
+
+static FIELDS: &[Field] = &[
+  Field {
+    name: "crystal_phi_deg",
+    get: SPDC::crystal_phi_deg,
+    set: SPDC::set_crystal_phi_deg,
+  },
+  Field {
+    name: "crystal_theta_deg",
+    get: SPDC::crystal_theta_deg,
+    set: SPDC::set_crystal_theta_deg,
+  },
+  Field {
+    name: "crystal_length_um",
+    get: SPDC::crystal_length_um,
+    set: SPDC::set_crystal_length_um,
+  },
+  Field {
+    name: "crystal_temperature_c",
+    get: SPDC::crystal_temperature_c,
+    set: SPDC::set_crystal_temperature_c,
+  },
+  Field {
+    name: "pump_wavelength_nm",
+    get: SPDC::pump_wavelength_nm,
+    set: SPDC::set_pump_wavelength_nm,
+  },
+  Field {
+    name: "pump_waist_nm",
+    get: SPDC::pump_waist_nm,
+    set: SPDC::set_pump_waist_nm,
+  },
+  Field {
+    name: "pump_bandwidth_nm",
+    get: SPDC::pump_bandwidth_nm,
+    set: SPDC::set_pump_bandwidth_nm,
+  },
+  Field {
+    name: "pump_average_power_mw",
+    get: SPDC::pump_average_power_mw,
+    set: SPDC::set_pump_average_power_mw,
+  },
+  Field {
+    name: "signal_wavelength_nm",
+    get: SPDC::signal_wavelength_nm,
+    set: SPDC::set_signal_wavelength_nm,
+  },
+  Field {
+    name: "signal_phi_deg",
+    get: SPDC::signal_phi_deg,
+    set: SPDC::set_signal_phi_deg,
+  },
+  Field {
+    name: "signal_theta_deg",
+    get: SPDC::signal_theta_deg,
+    set: SPDC::set_signal_theta_deg,
+  },
+  Field {
+    name: "signal_theta_external_deg",
+    get: SPDC::signal_theta_external_deg,
+    set: SPDC::set_signal_theta_external_deg,
+  },
+  Field {
+    name: "signal_waist_um",
+    get: SPDC::signal_waist_um,
+    set: SPDC::set_signal_waist_um,
+  },
+  Field {
+    name: "signal_waist_position_um",
+    get: SPDC::signal_waist_position_um,
+    set: SPDC::set_signal_waist_position_um,
+  },
+  Field {
+    name: "idler_wavelength_nm",
+    get: SPDC::idler_wavelength_nm,
+    set: SPDC::set_idler_wavelength_nm,
+  },
+  Field {
+    name: "idler_phi_deg",
+    get: SPDC::idler_phi_deg,
+    set: SPDC::set_idler_phi_deg,
+  },
+  Field {
+    name: "idler_theta_deg",
+    get: SPDC::idler_theta_deg,
+    set: SPDC::set_idler_theta_deg,
+  },
+  Field {
+    name: "idler_theta_external_deg",
+    get: SPDC::idler_theta_external_deg,
+    set: SPDC::set_idler_theta_external_deg,
+  },
+  Field {
+    name: "idler_waist_um",
+    get: SPDC::idler_waist_um,
+    set: SPDC::set_idler_waist_um,
+  },
+  Field {
+    name: "idler_waist_position_um",
+    get: SPDC::idler_waist_position_um,
+    set: SPDC::set_idler_waist_position_um,
+  },
+  Field {
+    name: "poling_period_um",
+    get: |spdc| spdc.poling_period_um().map_or(f64::NAN, f64::abs),
+    set: set_poling_period_magnitude_um,
+  },
+  Field {
+    name: "deff_pm_per_volt",
+    get: SPDC::deff_pm_per_volt,
+    set: SPDC::set_deff_pm_per_volt,
+  },
+];
+
+/// Set the magnitude of the poling period
+///
+/// Reversed poling stays reversed (and otherwise the sign phasematches, as when
+/// loading a config), so that sweeps and optimizations stay phasematched.
+fn set_poling_period_magnitude_um(spdc: &mut SPDC, value: f64) {
+  let period = if spdc.poling_is_reversed() {
+    -value.abs()
+  } else {
+    value.abs()
+  };
+  spdc.set_poling_period_um(Some(period));
+}
+
+impl Field {
+  /// Look up a field by its property name
+  pub(crate) fn by_name(name: &str) -> PyResult<&'static Field> {
+    FIELDS.iter().find(|f| f.name == name).ok_or_else(|| {
+      let names: Vec<_> = FIELDS.iter().map(|f| f.name).collect();
+      PyValueError::new_err(format!(
+        "Unknown SPDC field '{}'. Expected one of: {}",
+        name,
+        names.join(", ")
+      ))
+    })
+  }
+}
+
+/// A figure of merit that can be evaluated for an SPDC setup
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Metric {
+  SymmetricEfficiency,
+  SignalEfficiency,
+  IdlerEfficiency,
+  CoincidencesHz,
+  SignalSinglesHz,
+  IdlerSinglesHz,
+  SchmidtNumber,
+  InverseSchmidtNumber,
+  HeraldedPurity,
+  HomVisibility,
+}
+
+impl<'py> FromPyObject<'py> for Metric {
+  fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+    match ob.extract::<String>()?.as_str() {
+      "symmetric_efficiency" => Ok(Metric::SymmetricEfficiency),
+      "signal_efficiency" => Ok(Metric::SignalEfficiency),
+      "idler_efficiency" => Ok(Metric::IdlerEfficiency),
+      "coincidences_hz" => Ok(Metric::CoincidencesHz),
+      "signal_singles_hz" => Ok(Metric::SignalSinglesHz),
+      "idler_singles_hz" => Ok(Metric::IdlerSinglesHz),
+      "schmidt_number" => Ok(Metric::SchmidtNumber),
+      "inverse_schmidt_number" => Ok(Metric::InverseSchmidtNumber),
+      "heralded_purity" => Ok(Metric::HeraldedPurity),
+      "hom_visibility" => Ok(Metric::HomVisibility),
+      other => Err(PyValueError::new_err(format!(
+        "Unknown metric '{}'. Expected one of: symmetric_efficiency, signal_efficiency, \
+         idler_efficiency, coincidences_hz, signal_singles_hz, idler_singles_hz, \
+         schmidt_number, inverse_schmidt_number, heralded_purity, hom_visibility",
+        other
+      ))),
+    }
+  }
+}
+
+impl Metric {
+  /// Evaluate the metric for a setup over a frequency range
+  pub(crate) fn evaluate(
+    self,
+    spdc: &SPDC,
+    range: FrequencySpace,
+    integrator: ::spdcalc::math::Integrator,
+  ) -> Result<f64, PyKindError> {
+    use spdcalc::dim::ucum::HZ;
+    let efficiencies = || pump::efficiencies(spdc, range, integrator);
+    let spectrum = || pump::Spectrum::new(spdc, integrator);
+    Ok(match self {
+      Metric::SymmetricEfficiency => efficiencies().symmetric,
+      Metric::SignalEfficiency => efficiencies().signal,
+      Metric::IdlerEfficiency => efficiencies().idler,
+      Metric::CoincidencesHz => *(efficiencies().coincidences / HZ),
+      Metric::SignalSinglesHz => *(efficiencies().signal_singles / HZ),
+      Metric::IdlerSinglesHz => *(efficiencies().idler_singles / HZ),
+      Metric::SchmidtNumber => spectrum().schmidt_number(range)?,
+      Metric::InverseSchmidtNumber => 1. / spectrum().schmidt_number(range)?,
+      Metric::HeraldedPurity => {
+        let (x, y) = (range.steps().0 .2, range.steps().1 .2);
+        let jsa = spectrum().jsa_range(range);
+        SchmidtDecomposition::new(&jsa, x, y, 0)?.purity
+      }
+      Metric::HomVisibility => pump::hom_visibility(spdc, range, integrator).1,
+    })
+  }
+}
+
+/// Evaluate a metric for a setup, over a fixed range or the optimum range of the setup
+pub(crate) fn evaluate_metric(
+  spdc: &SPDC,
+  metric: Metric,
+  range: Option<FrequencySpace>,
+  integrator: ::spdcalc::math::Integrator,
+) -> Result<f64, PyKindError> {
+  let range = range.unwrap_or_else(|| spdc.0.optimum_range(DEFAULT_RESOLUTION));
+  metric.evaluate(spdc, range, integrator)
+}
+
+/// Number of steps per axis of the optimum range used when no range is given
+const DEFAULT_RESOLUTION: usize = 100;
+
+/// Evaluate a metric over a grid of SPDC field values
+///
+/// Each point of the grid is a copy of the given setup with the swept fields
+/// set (as with the property setters), and the points are evaluated in parallel.
+///
+/// Parameters
+/// ----------
+/// spdc : SPDC
+///     The setup to sweep
+/// fields : dict
+///     Mapping of SPDC property names (e.g. ``"crystal_temperature_c"``) to
+///     the values to sweep them over. The result has one axis per field, in order.
+/// metric : str, optional
+///     The quantity to evaluate. One of ``"symmetric_efficiency"`` (default),
+///     ``"signal_efficiency"``, ``"idler_efficiency"``, ``"coincidences_hz"``,
+///     ``"signal_singles_hz"``, ``"idler_singles_hz"``, ``"schmidt_number"``,
+///     ``"inverse_schmidt_number"``, ``"heralded_purity"`` or ``"hom_visibility"``.
+/// si_range : SIRange, optional
+///     Range of signal and idler frequencies. Defaults to the optimum range
+///     (with 100 steps) of each point of the sweep.
+/// integrator : Integrator, optional
+///     The integrator to use, which defaults to a simple Simpson's rule
+///
+/// Returns
+/// -------
+/// `numpy.ndarray` of `float`
+///     The metric at each point of the grid, with shape ``(len(values_1), len(values_2), ...)``
+#[pyfunction]
+#[pyo3(signature = (spdc, fields, metric = Metric::SymmetricEfficiency, si_range = None, integrator = None))]
+pub(crate) fn sweep<'py>(
+  py: Python<'py>,
+  spdc: &SPDC,
+  fields: &Bound<'py, PyDict>,
+  metric: Metric,
+  si_range: Option<SIRange>,
+  integrator: Option<Integrator>,
+) -> PyResult<Bound<'py, PyArrayDyn<f64>>> {
+  let axes = fields
+    .iter()
+    .map(|(name, values)| {
+      let field = Field::by_name(&name.extract::<String>()?)?;
+      let values = values.extract::<FloatArray<'_>>()?.map(|v| v);
+      Ok((field, values))
+    })
+    .collect::<PyResult<Vec<_>>>()?;
+  let shape: Vec<usize> = axes.iter().map(|(_, values)| values.len()).collect();
+  let range = si_range.map(FrequencySpace::try_from).transpose()?;
+  let integrator = integrator.unwrap_or_default().0;
+
+  let n_points: usize = shape.iter().product();
+  let results = py.allow_threads(|| {
+    (0..n_points)
+      .into_par_iter()
+      .map(|index| {
+        let mut point = spdc.clone();
+        // row-major: the last field varies fastest
+        let mut rest = index;
+        for (field, values) in axes.iter().rev() {
+          (field.set)(&mut point, values[rest % values.len()]);
+          rest /= values.len();
+        }
+        evaluate_metric(&point, metric, range, integrator)
+      })
+      .collect::<Result<Vec<f64>, _>>()
+  });
+  to_shaped_pyarray(py, results?, shape)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn fields_set_their_property() {
+    let mut spdc = SPDC::default();
+    (Field::by_name("crystal_temperature_c").unwrap().set)(&mut spdc, 42.);
+    assert!((spdc.crystal_temperature_c() - 42.).abs() < 1e-9);
+    (Field::by_name("poling_period_um").unwrap().set)(&mut spdc, 30.);
+    assert!((spdc.poling_period_um().unwrap() - 30.).abs() < 1e-9);
+    assert!(Field::by_name("not_a_field").is_err());
+  }
+
+  #[test]
+  fn poling_period_field_keeps_the_sign() {
+    let mut spdc = SPDC::default();
+    spdc.set_poling_period_um(Some(-30.));
+    (Field::by_name("poling_period_um").unwrap().set)(&mut spdc, 31.);
+    assert!(spdc.poling_is_reversed());
+    assert!((spdc.poling_period_um().unwrap() + 31.).abs() < 1e-9);
+  }
+
+  #[test]
+  fn metric_matches_direct_calculation() {
+    let spdc = SPDC::default();
+    let integrator = ::spdcalc::math::Integrator::default();
+    let range = spdc.0.optimum_range(5);
+    let expected = spdc.0.efficiencies(range, integrator).symmetric;
+    let actual =
+      evaluate_metric(&spdc, Metric::SymmetricEfficiency, Some(range), integrator).unwrap();
+    assert_eq!(actual, expected);
+  }
+}
@@ -0,0 +1,163 @@
+use crate::error::{ErrorKind, PyKindError};
+use crate::math::fwhm;
+use rustfft::FftPlanner;
+use spdcalc::Complex;
+
+/// Joint temporal amplitude computed from a JSA sampled on a frequency grid
+///
+/// Like the JSA it is stored with one row per idler time and one column per
+/// signal time, and is normalized so the squared magnitudes sum to one.
+pub(crate) struct JointTemporalAmplitude {
+  /// Flat row-major amplitudes
+  pub(crate) values: Vec<Complex<f64>>,
+  /// Signal time axis (seconds)
+  pub(crate) t_signal: Vec<f64>,
+  /// Idler time axis (seconds)
+  pub(crate) t_idler: Vec<f64>,
+}
+
+impl JointTemporalAmplitude {
+  /// Fourier transform a row-major JSA with `n_idler` rows and `n_signal`
+  /// columns, sampled with angular frequency steps `dw_signal` and `dw_idler`.
+  ///
+  /// The JSA is zero-padded to `padding` times its size along each axis
+  /// before transforming, which refines the time resolution.
+  pub(crate) fn from_jsa(
+    jsa: &[Complex<f64>],
+    (n_signal, dw_signal): (usize, f64),
+    (n_idler, dw_idler): (usize, f64),
+    padding: usize,
+  ) -> Result<Self, PyKindError> {
+    if n_signal < 2 || n_idler < 2 {
+      return Err(ErrorKind::Value.err("Frequency range needs at least two steps along each axis"));
+    }
+    if padding < 1 {
+      return Err(ErrorKind::Value.err("Padding must be at least 1"));
+    }
+    let (cols, rows) = (n_signal * padding, n_idler * padding);
+    let mut values = vec![Complex::new(0., 0.); rows * cols];
+    for (i, row) in jsa.chunks(n_signal).enumerate() {
+      values[i * cols..i * cols + n_signal].copy_from_slice(row);
+    }
+
+    let mut planner = FftPlanner::new();
+    // transform along the signal axis (rows are contiguous)
+    let fft = planner.plan_fft_forward(cols);
+    for row in values.chunks_mut(cols) {
+      fft.process(row);
+      row.rotate_right(cols / 2);
+    }
+    // then along the idler axis
+    let fft = planner.plan_fft_forward(rows);
+    let mut column = vec![Complex::new(0., 0.); rows];
+    for j in 0..cols {
+      column
+        .iter_mut()
+        .enumerate()
+        .for_each(|(i, c)| *c = values[i * cols + j]);
+      fft.process(&mut column);
+      column.rotate_right(rows / 2);
+      column
+        .iter()
+        .enumerate()
+        .for_each(|(i, c)| values[i * cols + j] = *c);
+    }
+
+    let norm = values.iter().map(|v| v.norm_sqr()).sum::<f64>().sqrt();
+    if norm > 0. {
+      values.iter_mut().for_each(|v| *v /= norm);
+    }
+
+    Ok(Self {
+      values,
+      t_signal: time_axis(cols, dw_signal),
+      t_idler: time_axis(rows, dw_idler),
+    })
+  }
+
+  /// The joint temporal intensity
+  pub(crate) fn intensities(&self) -> Vec<f64> {
+    self.values.iter().map(|v| v.norm_sqr()).collect()
+  }
+
+  /// FWHM of the distribution of signal-idler arrival time differences
+  ///
+  /// The JTI is binned by time difference with a bin width equal to the
+  /// smaller of the two time steps.
+  pub(crate) fn coincidence_window_fwhm(&self) -> Result<f64, PyKindError> {
+    let step = |t: &[f64]| t[1] - t[0];
+    let width = step(&self.t_signal).min(step(&self.t_idler));
+    let extent = |t: &[f64]| t[t.len() - 1].abs().max(t[0].abs());
+    let offset = ((extent(&self.t_signal) + extent(&self.t_idler)) / width).ceil() as i64;
+    let mut bins = vec![0.; 2 * offset as usize + 1];
+    let intensities = self.intensities();
+    for (row, t_i) in intensities.chunks(self.t_signal.len()).zip(&self.t_idler) {
+      for (jti, t_s) in row.iter().zip(&self.t_signal) {
+        let bin = ((t_s - t_i) / width).round() as i64 + offset;
+        bins[bin as usize] += jti;
+      }
+    }
+    let tau: Vec<f64> = (0..bins.len())
+      .map(|k| (k as i64 - offset) as f64 * width)
+      .collect();
+    fwhm(&tau, &bins).ok_or(
+      ErrorKind::Integration
+        .err("Coincidence distribution does not fall below half maximum within the time range"),
+    )
+  }
+}
+
+/// Time axis conjugate to `n` angular frequency steps of size `dw`,
+/// centered on zero to match a shifted FFT
+fn time_axis(n: usize, dw: f64) -> Vec<f64> {
+  let dt = 2. * std::f64::consts::PI / (n as f64 * dw);
+  (0..n).map(|k| (k as f64 - (n / 2) as f64) * dt).collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn coincidence_window_of_gaussian_jsa() {
+    // separable gaussian JSA with an intensity standard deviation of sigma
+    // along each axis, so each arrival time has a deviation of 1 / (2 sigma)
+    let (n, sigma) = (64, 1.);
+    let dw = 12. * sigma / (n - 1) as f64;
+    let w = |k: usize| -6. * sigma + k as f64 * dw;
+    let jsa: Vec<Complex<f64>> = (0..n)
+      .flat_map(|i| (0..n).map(move |s| (i, s)))
+      .map(|(i, s)| {
+        Complex::new(
+          (-(w(i).powi(2) + w(s).powi(2)) / (4. * sigma * sigma)).exp(),
+          0.,
+        )
+      })
+      .collect();
+    let jta = JointTemporalAmplitude::from_jsa(&jsa, (n, dw), (n, dw), 4).unwrap();
+    assert_eq!(jta.t_signal.len(), 4 * n);
+    let total: f64 = jta.intensities().iter().sum();
+    assert!((total - 1.).abs() < 1e-12);
+
+    let expected = 2. * (2. * 2f64.ln()).sqrt() * 2f64.sqrt() / (2. * sigma);
+    let fwhm = jta.coincidence_window_fwhm().unwrap();
+    assert!(
+      (fwhm - expected).abs() / expected < 0.02,
+      "{} != {}",
+      fwhm,
+      expected
+    );
+  }
+
+  #[test]
+  fn invalid_arguments_are_value_errors() {
+    let jsa = vec![Complex::new(1., 0.); 4];
+    let kind = |n: usize, padding: usize| {
+      JointTemporalAmplitude::from_jsa(&jsa, (n, 1.), (n, 1.), padding)
+        .err()
+        .map(|e| e.kind)
+    };
+    assert_eq!(kind(2, 0), Some(ErrorKind::Value));
+    assert_eq!(kind(1, 1), Some(ErrorKind::Value));
+  }
+}
@@ -0,0 +1,364 @@
+use crate::config::{parse_value, AutoFields, Extensions, Format, BEAMS};
+use crate::*;
+use ::spdcalc::dim::ucum::M;
+use ::spdcalc::SPDCConfig;
+use pyo3::types::PyDict;
+use serde_path_to_error::Segment;
+use serde_yaml::Value;
+
+/// Stop collecting type errors after this many, in case fixing them does not converge
+const MAX_TYPE_ERRORS: usize = 50;
+
+/// Fields that must be strictly positive
+const POSITIVE: &[&str] = &[
+  "crystal.length_um",
+  "pump.wavelength_nm",
+  "pump.waist_um",
+  "pump.bandwidth_nm",
+  "signal.wavelength_nm",
+  "signal.waist_um",
+  "idler.wavelength_nm",
+  "idler.waist_um",
+];
+
+/// How serious a problem with a config is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Severity {
+  /// The config can not be used
+  Error,
+  /// The config can be used, but likely does not describe a useful setup
+  Warning,
+}
+
+/// A problem with a config, at a config path if known
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Diagnostic {
+  pub(crate) severity: Severity,
+  pub(crate) problem: PyConfigError,
+}
+
+impl Diagnostic {
+  fn error(problem: PyConfigError) -> Self {
+    Self {
+      severity: Severity::Error,
+      problem,
+    }
+  }
+
+  fn warning(problem: PyConfigError) -> Self {
+    Self {
+      severity: Severity::Warning,
+      problem,
+    }
+  }
+}
+
+impl IntoPy<PyObject> for Diagnostic {
+  fn into_py(self, py: Python<'_>) -> PyObject {
+    let dict = PyDict::new_bound(py);
+    let severity = match self.severity {
+      Severity::Error => "error",
+      Severity::Warning => "warning",
+    };
+    let problem = self.problem;
+    // setting items of a new dict with string keys can not fail
+    dict.set_item("severity", severity).unwrap();
+    dict.set_item("path", problem.path).unwrap();
+    dict.set_item("message", problem.message).unwrap();
+    dict.set_item("line", problem.line).unwrap();
+    dict.set_item("column", problem.column).unwrap();
+    dict.into_py(py)
+  }
+}
+
+/// The value at a path of config keys
+fn at_path<'a>(value: &'a Value, path: &[&str]) -> Option<&'a Value> {
+  path.iter().try_fold(value, |value, key| value.get(key))
+}
+
+/// A number at a dotted config path
+fn number(value: &Value, path: &str) -> Option<f64> {
+  at_path(value, &path.split('.').collect::<Vec<_>>())?.as_f64()
+}
+
+/// Check the config against the types of `SPDCConfig`, collecting every type error
+///
+/// Deserialization stops at the first error, so each offending value is replaced
+/// by its default (or a missing field is filled in) and the check repeated.
+fn check_types(value: &mut Value, problems: &mut Vec<PyConfigError>) {
+  let defaults = serde_yaml::to_value(SPDCConfig::from(::spdcalc::SPDC::default()))
+    .expect("configs always serialize");
+  for _ in 0..MAX_TYPE_ERRORS {
+    let err = match serde_path_to_error::deserialize::<_, SPDCConfig>(value.clone()) {
+      Ok(_) => return,
+      Err(err) => err,
+    };
+    let mut path: Vec<String> = Vec::new();
+    for segment in err.path().iter() {
+      match segment {
+        Segment::Map { key } => path.push(key.clone()),
+        _ => break,
+      }
+    }
+    let mut problem = PyConfigError::from_yaml_path(err);
+    let missing = problem
+      .message
+      .split("missing field `")
+      .nth(1)
+      .and_then(|rest| rest.split('`').next())
+      .map(String::from);
+    if let Some(field) = missing {
+      path.push(field);
+      problem = PyConfigError::at(&path.join("."), "missing required field");
+    }
+    problems.push(problem);
+    if !replace_with_default(value, &defaults, &path) {
+      return;
+    }
+  }
+}
+
+/// Replace the value at a path with its default, returning whether anything changed
+fn replace_with_default(value: &mut Value, defaults: &Value, path: &[String]) -> bool {
+  let Some((key, parents)) = path.split_last() else {
+    return false;
+  };
+  let keys: Vec<&str> = path.iter().map(String::as_str).collect();
+  let default = at_path(defaults, &keys).cloned();
+  let parent = parents
+    .iter()
+    .try_fold(value, |value, key| value.get_mut(key.as_str()));
+  let Some(Value::Mapping(parent)) = parent else {
+    return false;
+  };
+  match default {
+    Some(default) if parent.get(key.as_str()) != Some(&default) => {
+      parent.insert(key.as_str().into(), default);
+      true
+    }
+    // without a default, removing the value reports it as missing instead
+    None => parent.remove(key.as_str()).is_some(),
+    _ => false,
+  }
+}
+
+/// Check values that parse but do not describe a physical setup
+fn check_values(value: &Value, extensions: &Extensions, problems: &mut Vec<PyConfigError>) {
+  for path in POSITIVE {
+    if let Some(v) = number(value, path).filter(|v| *v <= 0.) {
+      problems.push(PyConfigError::at(
+        path,
+        format!("must be positive, got {}", v),
+      ));
+    }
+  }
+  for (beam, y) in BEAMS.iter().zip(&extensions.waists_y_um) {
+    if let Some(y) = y.filter(|y| *y <= 0.) {
+      problems.push(PyConfigError::at(
+        &format!("{}.waist_um", beam),
+        format!("the y waist must be positive, got {}", y),
+      ));
+    }
+  }
+  if let Some(power) = number(value, "pump.average_power_mw").filter(|p| *p < 0.) {
+    problems.push(PyConfigError::at(
+      "pump.average_power_mw",
+      format!("must not be negative, got {}", power),
+    ));
+  }
+  if let Some(t) = number(value, "crystal.temperature_c").filter(|t| *t <= -273.15) {
+    problems.push(PyConfigError::at(
+      "crystal.temperature_c",
+      format!("must be above absolute zero, got {}", t),
+    ));
+  }
+  let Some(pump) = number(value, "pump.wavelength_nm").filter(|p| *p > 0.) else {
+    return;
+  };
+  for beam in ["signal", "idler"] {
+    let path = format!("{}.wavelength_nm", beam);
+    if let Some(wavelength) = number(value, &path).filter(|w| *w > 0. && *w <= pump) {
+      problems.push(PyConfigError::at(
+        &path,
+        format!(
+          "must be longer than the pump wavelength ({} nm), got {}",
+          pump, wavelength
+        ),
+      ));
+    }
+  }
+}
+
+/// Check a setup that can be built for physics problems
+fn check_physics(value: &Value, spdc: &SPDC, warnings: &mut Vec<PyConfigError>) {
+  if number(value, "deff_pm_per_volt") == Some(0.) {
+    warnings.push(PyConfigError::at(
+      "deff_pm_per_volt",
+      "is zero, so no pairs are produced",
+    ));
+  }
+  // the central frequencies should be within the main lobe of the phasematching sinc
+  let length = *(spdc.0.crystal_setup.length / M);
+  let mismatch = spdc.delta_k_z_at(spdc.crystal_temperature_c()) * length;
+  if mismatch.abs() < 2. * std::f64::consts::PI {
+    return;
+  }
+  let theta_is_auto =
+    at_path(value, &["crystal", "theta_deg"]).is_none_or(|theta| theta.as_str() == Some("auto"));
+  let poling_is_set = number(value, "periodic_poling.poling_period_um").is_some();
+  let (path, message) = if theta_is_auto && !poling_is_set {
+    ("crystal.theta_deg", "no phasematching solution for theta")
+  } else if poling_is_set {
+    (
+      "periodic_poling.poling_period_um",
+      "the poling period does not phasematch the setup",
+    )
+  } else {
+    ("crystal.theta_deg", "theta does not phasematch the setup")
+  };
+  warnings.push(PyConfigError::at(
+    path,
+    format!(
+      "{} (the phase mismatch is {:.3} rad over the crystal length)",
+      message, mismatch
+    ),
+  ));
+}
+
+/// The config path of an error from building a setup, if it has one
+fn build_error_path(err: &::spdcalc::SPDCError) -> Option<&'static str> {
+  if err.0.starts_with("Could not determine poling period") {
+    Some("periodic_poling.poling_period_um")
+  } else if err.0.starts_with("Can not autocalc theta") {
+    Some("crystal.theta_deg")
+  } else {
+    None
+  }
+}
+
+/// Validate config text, collecting every problem found
+///
+/// Type and value errors are collected first. Only a config without errors is
+/// built into a setup. Failing to build it is an error, otherwise the setup is
+/// checked for physics problems, which are warnings.
+pub(crate) fn validate_config(text: &str, format: Format) -> Vec<Diagnostic> {
+  let mut value = match parse_value(text, format) {
+    Ok(value) => value,
+    Err(problem) => return vec![Diagnostic::error(problem)],
+  };
+  let mut errors = Vec::new();
+  let extensions = Extensions::take(&mut value, &mut errors);
+  check_types(&mut value, &mut errors);
+  check_values(&value, &extensions, &mut errors);
+  if !errors.is_empty() {
+    return errors.into_iter().map(Diagnostic::error).collect();
+  }
+
+  let auto = AutoFields::of(&value);
+  let config: SPDCConfig = serde_yaml::from_value(value.clone()).expect("types were checked above");
+  let mut spdc = match config.try_as_spdc() {
+    Ok(spdc) => spdc,
+    // a config that can not be built is rejected by `SPDC.from_yaml`, so this is
+    // an error even if the cause is phasematching
+    Err(err) => {
+      let problem = match build_error_path(&err) {
+        Some(path) => PyConfigError::at(path, err),
+        None => PyConfigError::new(err.0),
+      };
+      return vec![Diagnostic::error(problem)];
+    }
+  };
+  let pump_spectrum = extensions.apply(&mut spdc, auto.idler);
+  let mut warnings = Vec::new();
+  check_physics(&value, &SPDC(spdc, pump_spectrum, auto), &mut warnings);
+  warnings.into_iter().map(Diagnostic::warning).collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  const CONFIG: &str = "
+crystal:
+  kind: KTP
+  pm_type: e->eo
+  theta_deg: 90
+  length_um: 14000
+  temperature_c: 20
+pump:
+  wavelength_nm: 775
+  waist_um: 200
+  bandwidth_nm: 0.5
+  average_power_mw: 300
+signal:
+  wavelength_nm: 1550
+  theta_external_deg: 0
+  waist_um: 100
+idler: auto
+periodic_poling:
+  poling_period_um: auto
+deff_pm_per_volt: 7.6
+";
+
+  fn paths(diagnostics: &[Diagnostic], severity: Severity) -> Vec<&str> {
+    diagnostics
+      .iter()
+      .filter(|d| d.severity == severity)
+      .filter_map(|d| d.problem.path.as_deref())
+      .collect()
+  }
+
+  #[test]
+  fn valid_config_has_no_diagnostics() {
+    assert_eq!(validate_config(CONFIG, Format::Yaml), vec![]);
+  }
+
+  #[test]
+  fn all_type_errors_are_collected() {
+    let yaml = CONFIG
+      .replace("length_um: 14000", "length_um: long")
+      .replace("waist_um: 100", "waist_um: wide")
+      .replace("  bandwidth_nm: 0.5\n", "");
+    let diagnostics = validate_config(&yaml, Format::Yaml);
+    assert_eq!(
+      paths(&diagnostics, Severity::Error),
+      vec!["crystal.length_um", "pump.bandwidth_nm", "signal.waist_um"]
+    );
+  }
+
+  #[test]
+  fn unphysical_values_are_errors() {
+    let yaml = CONFIG
+      .replace("length_um: 14000", "length_um: -1")
+      .replace("waist_um: 200", "waist_um: [200, 0]")
+      .replace("wavelength_nm: 1550", "wavelength_nm: 700");
+    let diagnostics = validate_config(&yaml, Format::Yaml);
+    assert_eq!(
+      paths(&diagnostics, Severity::Error),
+      vec!["crystal.length_um", "pump.waist_um", "signal.wavelength_nm"]
+    );
+  }
+
+  #[test]
+  fn phasematching_problems_are_warnings() {
+    let yaml = CONFIG.replace("poling_period_um: auto", "poling_period_um: 5");
+    let diagnostics = validate_config(&yaml, Format::Yaml);
+    assert_eq!(
+      paths(&diagnostics, Severity::Warning),
+      vec!["periodic_poling.poling_period_um"]
+    );
+    assert!(paths(&diagnostics, Severity::Error).is_empty());
+  }
+
+  #[test]
+  fn setups_that_can_not_be_built_are_errors() {
+    let yaml = CONFIG.replace("theta_deg: 90", "theta_deg: auto");
+    assert!(SPDC::from_yaml(&yaml).is_err());
+    let diagnostics = validate_config(&yaml, Format::Yaml);
+    assert_eq!(
+      paths(&diagnostics, Severity::Error),
+      vec!["crystal.theta_deg"]
+    );
+    assert!(paths(&diagnostics, Severity::Warning).is_empty());
+  }
+}
@@ -0,0 +1,40 @@
+from spdcalc import SPDC
+
+config = """
+crystal:
+  kind: KTP
+  pm_type: e->eo
+  phi_deg: 0
+  theta_deg: 90
+  length_um: 14000
+  temperature_c: 40
+pump:
+  wavelength_nm: 775
+  waist_um: 200
+  bandwidth_nm: 0.5
+  average_power_mw: 300
+signal:
+  wavelength_nm: 1550
+  phi_deg: 0
+  theta_external_deg: 0
+  waist_um: 100
+  waist_position_um: auto
+idler: auto
+periodic_poling:
+  poling_period_um: auto
+deff_pm_per_volt: 7.6
+"""
+
+spdc = SPDC.from_yaml(config)
+period = spdc.poling_period_um
+assert period > 0
+
+# a negative period reverses the sign of the poling, and is written to configs
+spdc.poling_period_um = -period
+assert spdc.poling_period_um == -period
+loaded = SPDC.from_yaml(spdc.to_yaml())
+assert loaded.poling_period_um < 0
+assert abs(loaded.poling_period_um + period) < 1e-3 * period
+assert loaded != SPDC.from_yaml(config)
+
+print("ok")